@@ -2,8 +2,8 @@ use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 use std::env::current_dir;
 use std::fs::create_dir_all;
 
-use cw_escrow::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use cw_escrow::state::State;
+use buytoken::msg::{BuyTokenResponse, ExecuteMsg, InstantiateMsg, PurchaseHookMsg, QueryMsg, SudoMsg};
+use buytoken::state::State;
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -14,5 +14,8 @@ fn main() {
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(SudoMsg), &out_dir);
+    export_schema(&schema_for!(BuyTokenResponse), &out_dir);
+    export_schema(&schema_for!(PurchaseHookMsg), &out_dir);
     export_schema(&schema_for!(State), &out_dir);
 }
@@ -1,33 +1,65 @@
 use cosmwasm_std::{
-    entry_point, to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Response, QuerierWrapper,
-    Uint128, Uint64, CosmosMsg,
-    StdResult,
+    entry_point, from_binary, to_binary, Addr, Api, Binary, Decimal, Deps, DepsMut, Env, MessageInfo, Response, QuerierWrapper,
+    Uint128, Uint64, CosmosMsg, Coin, DistributionMsg, StakingMsg, WasmMsg, Reply, SubMsg, Timestamp,
+    StdError, StdResult, Storage, Order,
 };
+use cw_storage_plus::Bound;
 
-use cw20::Denom;
+use cw20::{Balance, Denom};
 
 use crate::error::ContractError;
-use crate::msg::{AdminResponse, ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{config, config_read, State, BOT_ROLES};
+use crate::msg::{
+    AdminResponse, BalancesResponse, BuybackResponse, BuyTokenResponse, ConfigResponse, DelegationResponse, DenomBalance, HolderDiscountResponse, LoyaltyResponse, ExecuteMsg, FeeScheduleResponse,
+    GasReimbursementResponse, IbcLifecycleComplete, IbcTransferResponse, InstantiateMsg, IntentNonceResponse, LiquidityPositionResponse, PoolResponse,
+    PoolsResponse, PoolVolume, PurchaseHookMsg, QueryMsg, ReceiveMsg, RecipientFeeResponse, SignedBuyIntent,
+    SimulateBuyExactOutResponse, SimulateBuyResponse, StakingResponse, StatsResponse, StatusResponse, SudoMsg, TradesResponse, UserBalancesResponse,
+    VestingResponse, VestingsResponse,
+};
+use crate::state::{
+    config, config_read, denom_key, trades, BuybackConfig, DenomTotal, FeeConfig, FeeTier, GasConfig, HolderDiscountConfig, HolderDiscountTier, LoyaltyConfig, LoyaltyEpoch, IbcSend, LiquidityConfig, PendingCompound, PendingOutput, PendingRemoveLiquidity, PoolInfo,
+    PurchaseCallback, State, Trade, Vesting, VestingTerms, BOT_ROLES, BUYBACK_CONFIG, FEE_CONFIG, FEES_ACCRUED_BY_DENOM, FEES_WITHDRAWN_BY_DENOM,
+    FEE_OVERRIDES, GAS_CONFIG, GAS_LEDGERS, HOLDER_DISCOUNT, IBC_TRANSFERS, INTENT_KEYS, INTENT_NONCES, LIQUIDITY_CONFIG, LIQUID_RESERVE, LOYALTY_CONFIG, LOYALTY_EPOCH, PENDING_GAS_REIMBURSEMENT, PENDING_IBC_SEND,
+    PENDING_BURN, PENDING_COMPOUND, PENDING_OUTPUT, PENDING_REMOVE_LIQUIDITY, POOLS, POOL_VOLUMES, RECIPIENT_VOLUMES, STATS, TRADE_COUNT, USER_BALANCES, USER_BALANCE_TOTALS, VESTINGS, VESTING_TOTALS,
+    TOTAL_BURNED, VESTING_COUNT, VOLUME_BY_DENOM,
+};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw_storage_plus::Map;
+use crate::events;
 use crate::util;
+use crate::venue::{self, PoolRef, Venue};
 
 //const GAS_MAX: u128 = 2000u128;
 const ATOM_DENOM: &str = "ibc/C4CFF46FD6DE35CA4CF4CE031E643C8FDC9BA4B99AE598E9B0ED98FE3A2319F9"; //ibc atom token
 const ATOM_JUNO_POOL_ADDR: &str = "juno1sg6chmktuhyj4lsrxrrdflem7gsnk4ejv6zkcc4d3vcqulzp55wsf4l4gl";
+const MAX_BIPS: u128 = 10000u128;
+const SPLIT_STEPS: u128 = 20u128;
+const MAX_IBC_TIMEOUT: u64 = 7 * 24 * 60 * 60;
+
+const REPLY_IBC_SWAP: u64 = 1;
+const REPLY_IBC_TRANSFER: u64 = 2;
+const REPLY_BUY_OUTPUT: u64 = 3;
+const REPLY_BUYBACK: u64 = 4;
+const REPLY_COMPOUND: u64 = 5;
+const REPLY_REMOVE_LIQUIDITY: u64 = 6;
 
 #[entry_point]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
-    _msg: InstantiateMsg,
+    msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     let state = State {
         owner: info.sender.clone(),
         pending_platform_fee: Uint128::zero(),
     };
 
+    let fee_config = validate_fee_config(msg.default_fee_bips, msg.max_fee_bips, vec![])?;
+
     config(deps.storage).save(&state)?;
+    FEE_CONFIG.save(deps.storage, &fee_config)?;
+    GAS_CONFIG.save(deps.storage, &GasConfig { gas_price: msg.gas_price, max_gas: msg.max_gas })?;
+    PENDING_GAS_REIMBURSEMENT.save(deps.storage, &Uint128::zero())?;
     Ok(Response::default())
 }
 
@@ -40,51 +72,205 @@ pub fn execute(
 ) -> Result<Response, ContractError> {
     let mut state = config_read(deps.storage).load()?;
     match msg {
-        ExecuteMsg::SetAdmin { new_admin } => try_set_admin(deps, &mut state, info, new_admin),
-        ExecuteMsg::SetBotRole { new_bot, enabled } => try_set_bot_role(deps, state, info, new_bot, enabled),
-        ExecuteMsg::BuyToken {juno_amount, token_amount_per_native, slippage_bips, recipient, pool_address, platform_fee_bips, gas_estimate, deadline} => 
-                buy_token(deps, &mut state, info, env, juno_amount, token_amount_per_native, slippage_bips, recipient, pool_address, platform_fee_bips, gas_estimate, deadline),      
-        ExecuteMsg::WithdrawFee { to, amount } => try_withdraw_fee(deps, &mut state, info, to, amount),
+        ExecuteMsg::SetAdmin { new_admin } => {
+            let new_admin = validate_address(deps.api, &new_admin, "new_admin")?;
+            try_set_admin(deps, &mut state, info, new_admin)
+        },
+        ExecuteMsg::SetBotRole { new_bot, enabled } => {
+            let new_bot = validate_address(deps.api, &new_bot, "new_bot")?;
+            try_set_bot_role(deps, state, info, new_bot, enabled)
+        },
+        ExecuteMsg::BuyToken {juno_amount, token_amount_per_native, slippage_bips, recipient, pool_address, platform_fee_bips, gas_estimate, deadline, intent, callback} => {
+            let recipient = validate_address(deps.api, &recipient, "recipient")?;
+            let pool_address = validate_address(deps.api, &pool_address, "pool_address")?;
+            let params = BuyParams { juno_amount, token_amount_per_native, slippage_bips, recipient, platform_fee_bips, gas_estimate, deadline };
+            buy_token(deps, info, env, params, pool_address, intent.map(|intent| *intent), callback)
+        },
+        ExecuteMsg::BuyTokenBestPool {juno_amount, token_amount_per_native, slippage_bips, recipient, target_denom, platform_fee_bips, gas_estimate, deadline} => {
+            let recipient = validate_address(deps.api, &recipient, "recipient")?;
+            let params = BuyParams { juno_amount, token_amount_per_native, slippage_bips, recipient, platform_fee_bips, gas_estimate, deadline };
+            buy_token_best_pool(deps, info, env, params, target_denom)
+        },
+        ExecuteMsg::BuyTokenSplit {juno_amount, token_amount_per_native, slippage_bips, recipient, target_denom, max_pools, platform_fee_bips, gas_estimate, deadline} => {
+            let recipient = validate_address(deps.api, &recipient, "recipient")?;
+            let params = BuyParams { juno_amount, token_amount_per_native, slippage_bips, recipient, platform_fee_bips, gas_estimate, deadline };
+            buy_token_split(deps, info, env, params, target_denom, max_pools)
+        },
+        ExecuteMsg::WithdrawFee { to, amount } => {
+            let to = validate_address(deps.api, &to, "to")?;
+            try_withdraw_fee(deps, &mut state, info, env, to, amount)
+        },
+        ExecuteMsg::BuyTokenExactOut {desired_output, recipient, pool_address, platform_fee_bips, gas_estimate, deadline} => {
+            let recipient = validate_address(deps.api, &recipient, "recipient")?;
+            let pool_address = validate_address(deps.api, &pool_address, "pool_address")?;
+            // the desired output is the only price floor, the attached ujuno the most the buy may spend
+            let juno_amount = util::get_amount_of_denom(Balance::from(info.funds.clone()), Denom::Native(String::from("ujuno")))?;
+            let params = BuyParams { juno_amount, token_amount_per_native: Uint128::zero(), slippage_bips: Uint128::zero(), recipient, platform_fee_bips, gas_estimate, deadline };
+            buy_token_exact_out(deps, info, env, params, pool_address, desired_output)
+        },
         ExecuteMsg::SwapAtomToJuno {} => try_swap_atom(deps, &mut state, env, info),
+        ExecuteMsg::SetFeeConfig { default_bips, max_bips, tiers } => try_set_fee_config(deps, state, info, default_bips, max_bips, tiers),
+        ExecuteMsg::SetFeeOverride { recipient, fee_bips } => {
+            let recipient = validate_address(deps.api, &recipient, "recipient")?;
+            try_set_fee_override(deps, state, info, recipient, fee_bips)
+        },
+        ExecuteMsg::SetHolderDiscount { token, tiers, nft_collection, nft_discount_bips } => {
+            let token = token.map(|token| validate_address(deps.api, &token, "token")).transpose()?;
+            let nft_collection = nft_collection.map(|collection| validate_address(deps.api, &collection, "nft_collection")).transpose()?;
+            try_set_holder_discount(deps, state, info, token, tiers, nft_collection, nft_discount_bips)
+        },
+        ExecuteMsg::SetGasConfig { gas_price, max_gas } => try_set_gas_config(deps, state, info, gas_price, max_gas),
+        ExecuteMsg::ClaimGasReimbursement {} => try_claim_gas_reimbursement(deps, info, env),
+        ExecuteMsg::RegisterPool { pool_address, venue } => {
+            let pool_address = validate_address(deps.api, &pool_address, "pool_address")?;
+            try_register_pool(deps, state, info, pool_address, venue.unwrap_or_default())
+        },
+        ExecuteMsg::SetPoolEnabled { pool_address, enabled } => {
+            let pool_address = validate_address(deps.api, &pool_address, "pool_address")?;
+            try_set_pool_enabled(deps, state, info, pool_address, enabled)
+        },
+        ExecuteMsg::Deposit {} => {
+            let amount = util::get_amount_of_denom(Balance::from(info.funds), Denom::Native(String::from("ujuno")))?;
+            try_deposit(deps, info.sender, Denom::Native(String::from("ujuno")), amount)
+        },
+        ExecuteMsg::Receive(msg) => try_receive(deps, &mut state, info, env, msg),
+        ExecuteMsg::Withdraw { denom, amount } => try_withdraw(deps, info, denom, amount),
+        ExecuteMsg::SetIntentKey { pubkey } => try_set_intent_key(deps, info, pubkey),
+        ExecuteMsg::BuyTokenVested {juno_amount, token_amount_per_native, slippage_bips, recipient, pool_address, platform_fee_bips, gas_estimate, deadline, cliff, duration} => {
+            let recipient = validate_address(deps.api, &recipient, "recipient")?;
+            let pool_address = validate_address(deps.api, &pool_address, "pool_address")?;
+            let params = BuyParams { juno_amount, token_amount_per_native, slippage_bips, recipient, platform_fee_bips, gas_estimate, deadline };
+            buy_token_vested(deps, info, env, params, pool_address, VestingTerms { cliff, duration })
+        },
+        ExecuteMsg::ClaimVested {} => try_claim_vested(deps, info, env),
+        ExecuteMsg::SetBuybackConfig { pool_address, max_slippage_bips } => {
+            let pool_address = validate_address(deps.api, &pool_address, "pool_address")?;
+            try_set_buyback_config(deps, state, info, pool_address, max_slippage_bips)
+        },
+        ExecuteMsg::BuybackAndBurn { amount } => try_buyback_and_burn(deps, &mut state, info, env, amount),
+        ExecuteMsg::SetLiquidityConfig { pool_address, max_slippage_bips } => {
+            let pool_address = validate_address(deps.api, &pool_address, "pool_address")?;
+            try_set_liquidity_config(deps, state, info, pool_address, max_slippage_bips)
+        },
+        ExecuteMsg::CompoundFeesToLiquidity { amount } => try_compound_fees_to_liquidity(deps, &mut state, info, env, amount),
+        ExecuteMsg::RemoveLiquidity { amount, min_token1, min_token2, to } => {
+            let to = validate_address(deps.api, &to, "to")?;
+            try_remove_liquidity(deps, info, env, amount, min_token1, min_token2, to)
+        },
+        ExecuteMsg::SetLoyaltyConfig { token, points_per_ujuno, ujuno_per_point, epoch_seconds, epoch_cap } => {
+            let token = validate_address(deps.api, &token, "token")?;
+            try_set_loyalty_config(deps, state, info, env, LoyaltyConfig { token, points_per_ujuno, ujuno_per_point, epoch_seconds, epoch_cap })
+        },
+        ExecuteMsg::SetLiquidReserve { amount } => try_set_liquid_reserve(deps, state, info, amount),
+        ExecuteMsg::Delegate { validator, amount } => try_delegate(deps, &mut state, info, env, validator, amount),
+        ExecuteMsg::Undelegate { validator, amount } => try_undelegate(deps, &mut state, info, env, validator, amount),
+        ExecuteMsg::Redelegate { src_validator, dst_validator, amount } => try_redelegate(deps, &mut state, info, env, src_validator, dst_validator, amount),
+        ExecuteMsg::ClaimStakingRewards { validator } => try_claim_staking_rewards(deps, &mut state, info, env, validator),
+        ExecuteMsg::BuyTokenAndIbcSend { token_amount_per_native, slippage_bips, pool_address, platform_fee_bips, deadline, channel, remote_recipient, timeout } => {
+            let pool_address = validate_address(deps.api, &pool_address, "pool_address")?;
+            // anything but ujuno would be stuck, neither spent nor refunded
+            if info.funds.iter().any(|coin| coin.denom != "ujuno") {
+                return Err(ContractError::UnsupportedDenom { });
+            }
+            let juno_amount = util::get_amount_of_denom(Balance::from(info.funds), Denom::Native(String::from("ujuno")))?;
+            let params = BuyParams { juno_amount, token_amount_per_native, slippage_bips, recipient: info.sender, platform_fee_bips, gas_estimate: Uint128::zero(), deadline };
+            buy_token_and_ibc_send(deps, env, params, pool_address, channel, remote_recipient, timeout)
+        },
     }
 }
 
+fn validate_address(api: &dyn Api, addr: &str, field: &str) -> Result<Addr, ContractError> {
+    api.addr_validate(addr)
+        .map_err(|_| ContractError::InvalidAddress { field: String::from(field) })
+}
+
 fn try_swap_atom(    
     deps: DepsMut,
     _state: &mut State,
     env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
 )-> Result<Response, ContractError> {
-    let messags = get_message_swap_atom(deps.querier, env,  String::from(ATOM_DENOM), Addr::unchecked(ATOM_JUNO_POOL_ADDR))?;
+    let (messags, sweep) = sweep_atom(deps.querier, deps.storage, &env, &info.sender)?;
 
-    Ok(Response::new()
-        .add_messages(messags))
+    let mut response = Response::new()
+        .add_attribute("action", "swap_atom");
+    match sweep {
+        Some(trade) => {
+            save_pending_output(deps.querier, deps.storage, &trade, env.contract.address.clone(), None, None, None)?;
+            response = response
+                .add_submessages(reply_on_last(messags, REPLY_BUY_OUTPUT))
+                .add_event(events::sweep_event(&trade));
+        },
+        None => response = response.add_messages(messags),
+    }
+    Ok(response)
+}
+
+/// Builds the swap of the contract's ATOM into ujuno and records it as a trade, returning the trade when
+/// there was ATOM to swap. ATOM credited to users or vested for recipients is not the contract's and stays.
+fn sweep_atom(
+    querier: QuerierWrapper,
+    storage: &mut dyn Storage,
+    env: &Env,
+    sender: &Addr,
+) -> Result<(Vec<CosmosMsg>, Option<Trade>), ContractError> {
+    let pool = Addr::unchecked(ATOM_JUNO_POOL_ADDR);
+    let key = denom_key(&Denom::Native(String::from(ATOM_DENOM)));
+    let reserved = USER_BALANCE_TOTALS.may_load(storage, &key)?.unwrap_or_default()
+        + VESTING_TOTALS.may_load(storage, &key)?.unwrap_or_default();
+    let (atom_amount, juno_amount, messages) = get_message_swap_atom(querier, env.clone(), String::from(ATOM_DENOM), pool.clone(), reserved)?;
+
+    if atom_amount.is_zero() {
+        return Ok((messages, None));
+    }
+
+    let trade = save_trade(storage, Trade {
+            id: 0,
+            bot: sender.clone(),
+            recipient: env.contract.address.clone(),
+            pool,
+            block_height: env.block.height,
+            block_time: env.block.time.seconds(),
+            input_denom: Denom::Native(String::from(ATOM_DENOM)),
+            input_amount: atom_amount,
+            platform_fee: Uint128::zero(),
+            gas_reimbursement: Uint128::zero(),
+            quoted_output: juno_amount,
+            amount_out_min: Uint128::zero(),
+            output_denom: Denom::Native(String::from("ujuno")),
+        })?;
+    Ok((messages, Some(trade)))
 }
 
+/// Returns the ATOM swapped, the balance above `reserved`, the ujuno it is quoted to return and the swap messages
 fn get_message_swap_atom(    
     querier: QuerierWrapper,
     env: Env,
     token: String,
-    pool_address: Addr
-)-> Result<Vec<CosmosMsg>, ContractError> {
+    pool_address: Addr,
+    reserved: Uint128,
+)-> Result<(Uint128, Uint128, Vec<CosmosMsg>), ContractError> {
     let mut messages: Vec<CosmosMsg> = vec![];
     
-    let token_balance = util::get_token_amount(querier, Denom::Native(token.clone()), env.contract.address)?;
+    let token_balance = util::get_token_amount(querier, Denom::Native(token.clone()), env.contract.address)?
+        .saturating_sub(reserved);
 
     if token_balance == Uint128::zero() {
-        return Ok(messages);
+        return Ok((token_balance, Uint128::zero(), messages));
     }
 
-    let (_token2_amount, _token2_denom, mut messages_swap) = 
+    let pool = util::get_wasmswap_pool(querier, pool_address.clone())?;
+    let (token2_amount, _token2_denom, mut messages_swap) = 
         util::get_swap_amount_and_denom_and_message(querier
             , pool_address
+            , &pool
             , Denom::Native(token)
             , token_balance
             , Uint128::zero()
             , None)?;
     messages.append(&mut messages_swap);    
 
-    Ok(messages)
+    Ok((token_balance, token2_amount, messages))
 }
 
 fn try_set_admin(
@@ -97,11 +283,13 @@ fn try_set_admin(
         return Err(ContractError::Unauthorized { });
     }
 
-    state.owner = new_admin.clone();
+    let previous_admin = std::mem::replace(&mut state.owner, new_admin.clone());
     config(deps.storage).save(&state)?;
 
     Ok(Response::new()
-        .add_attribute("new_admin", new_admin)
+        .add_attribute("action", "set_admin")
+        .add_attribute("new_admin", new_admin.clone())
+        .add_event(events::admin_change_event(&previous_admin, &new_admin))
     )
 }
 
@@ -119,115 +307,2654 @@ fn try_set_bot_role(
     BOT_ROLES.save(deps.storage, new_bot.clone(), &role)?;
     
     Ok(Response::new()
-        .add_attribute("bot added", "Yes")
+        .add_attribute("action", "set_bot_role")
+        .add_attribute("bot", new_bot.clone())
+        .add_attribute("enabled", role.to_string())
+        .add_event(events::bot_role_event(&new_bot, role))
     )
 }
 
-fn try_withdraw_fee(
+fn validate_fee_config(
+    default_bips: Uint128,
+    max_bips: Uint128,
+    mut tiers: Vec<FeeTier>
+) -> Result<FeeConfig, ContractError> {
+    if max_bips > Uint128::from(MAX_BIPS) || default_bips > max_bips {
+        return Err(ContractError::InvalidFeeConfig { });
+    }
+    if tiers.iter().any(|tier| tier.fee_bips > max_bips) {
+        return Err(ContractError::InvalidFeeConfig { });
+    }
+
+    tiers.sort_by_key(|tier| tier.min_volume);
+    if tiers.windows(2).any(|pair| pair[0].min_volume == pair[1].min_volume) {
+        return Err(ContractError::InvalidFeeConfig { });
+    }
+
+    Ok(FeeConfig { default_bips, max_bips, tiers })
+}
+
+fn try_set_fee_config(
     deps: DepsMut,
-    state: &mut State,
+    state: State,
     info: MessageInfo,
-    to: Addr,
-    amount: Uint128
+    default_bips: Uint128,
+    max_bips: Uint128,
+    tiers: Vec<FeeTier>
 ) -> Result<Response, ContractError> {
     if state.owner != info.sender {
         return Err(ContractError::Unauthorized { });
     }
 
-    state.pending_platform_fee -= amount;
+    let fee_config = validate_fee_config(default_bips, max_bips, tiers)?;
+    // an override above the new cap would keep charging past it
+    for item in FEE_OVERRIDES.range(deps.storage, None, None, Order::Ascending) {
+        let (_, fee_bips) = item?;
+        if fee_bips > fee_config.max_bips {
+            return Err(ContractError::InvalidFeeConfig { });
+        }
+    }
+    FEE_CONFIG.save(deps.storage, &fee_config)?;
 
-    config(deps.storage).save(&state)?;
+    Ok(Response::new()
+        .add_attribute("action", "set_fee_config")
+        .add_attribute("default_bips", default_bips)
+        .add_attribute("max_bips", max_bips)
+    )
+}
 
-    let mut msgs: Vec<CosmosMsg> = vec![];
+fn try_set_fee_override(
+    deps: DepsMut,
+    state: State,
+    info: MessageInfo,
+    recipient: Addr,
+    fee_bips: Option<Uint128>
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
 
-    msgs.push(util::transfer_token_message(Denom::Native(String::from("ujuno")), amount, to)?);
+    match fee_bips {
+        Some(fee_bips) => {
+            let fee_config = FEE_CONFIG.load(deps.storage)?;
+            if fee_bips > fee_config.max_bips {
+                return Err(ContractError::InvalidFeeConfig { });
+            }
+            FEE_OVERRIDES.save(deps.storage, recipient.clone(), &fee_bips)?;
+        }
+        None => {
+            FEE_OVERRIDES.remove(deps.storage, recipient.clone());
+        }
+    }
 
     Ok(Response::new()
-        .add_messages(msgs)
+        .add_attribute("action", "set_fee_override")
+        .add_attribute("recipient", recipient)
+        .add_attribute("fee_bips", fee_bips.map_or(String::from("none"), |bips| bips.to_string()))
     )
 }
 
-fn buy_token(
+fn try_set_holder_discount(
+    deps: DepsMut,
+    state: State,
+    info: MessageInfo,
+    token: Option<Addr>,
+    mut tiers: Vec<HolderDiscountTier>,
+    nft_collection: Option<Addr>,
+    nft_discount_bips: Uint128,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+
+    if nft_discount_bips > Uint128::from(MAX_BIPS) || tiers.iter().any(|tier| tier.discount_bips > Uint128::from(MAX_BIPS)) {
+        return Err(ContractError::InvalidFeeConfig { });
+    }
+    if token.is_none() && !tiers.is_empty() {
+        return Err(ContractError::InvalidFeeConfig { });
+    }
+    tiers.sort_by_key(|tier| tier.min_balance);
+    if tiers.windows(2).any(|pair| pair[0].min_balance == pair[1].min_balance) {
+        return Err(ContractError::InvalidFeeConfig { });
+    }
+
+    let discount = HolderDiscountConfig { token, tiers, nft_collection, nft_discount_bips };
+    HOLDER_DISCOUNT.save(deps.storage, &discount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_holder_discount")
+        .add_attribute("token", discount.token.map_or(String::from("none"), |token| token.to_string()))
+        .add_attribute("nft_collection", discount.nft_collection.map_or(String::from("none"), |collection| collection.to_string()))
+        .add_attribute("nft_discount_bips", nft_discount_bips)
+    )
+}
+
+/// Returns the largest discount the holder qualifies for, by token balance or NFT ownership
+fn get_holder_discount_bips(
+    querier: QuerierWrapper,
+    storage: &dyn Storage,
+    holder: &Addr,
+) -> StdResult<Uint128> {
+    let discount = match HOLDER_DISCOUNT.may_load(storage)? {
+        Some(discount) => discount,
+        None => return Ok(Uint128::zero()),
+    };
+
+    let mut discount_bips = Uint128::zero();
+    if let Some(token) = discount.token {
+        if !discount.tiers.is_empty() {
+            let balance = util::get_token_amount(querier, Denom::Cw20(token), holder.clone())?;
+            discount_bips = discount.tiers.iter()
+                .rev()
+                .find(|tier| balance >= tier.min_balance)
+                .map_or(Uint128::zero(), |tier| tier.discount_bips);
+        }
+    }
+    if let Some(collection) = discount.nft_collection {
+        if discount.nft_discount_bips > discount_bips && util::owns_nft(querier, collection, holder.clone())? {
+            discount_bips = discount.nft_discount_bips;
+        }
+    }
+
+    Ok(discount_bips)
+}
+
+/// Scheduled bips for a recipient after its holder discount, with its volume, whether an override applied
+/// and the discount. Overrides are charged as set
+fn get_discounted_fee_bips(
+    querier: QuerierWrapper,
+    storage: &dyn Storage,
+    fee_config: &FeeConfig,
+    recipient: &Addr,
+) -> StdResult<(Uint128, Uint128, bool, Uint128)> {
+    let (fee_bips, volume, overridden) = get_scheduled_fee_bips(storage, fee_config, recipient.clone())?;
+    if overridden {
+        return Ok((fee_bips, volume, overridden, Uint128::zero()));
+    }
+
+    let discount_bips = get_holder_discount_bips(querier, storage, recipient)?;
+    let fee_bips = fee_bips * (Uint128::from(MAX_BIPS) - discount_bips) / Uint128::from(MAX_BIPS);
+    Ok((fee_bips, volume, overridden, discount_bips))
+}
+
+/// Returns the scheduled bips for a recipient, its cumulative volume and whether an override applied.
+/// An override wins over the volume tiers, which win over the default.
+fn get_scheduled_fee_bips(
+    storage: &dyn Storage,
+    fee_config: &FeeConfig,
+    recipient: Addr
+) -> StdResult<(Uint128, Uint128, bool)> {
+    let volume = RECIPIENT_VOLUMES.may_load(storage, recipient.clone())?.unwrap_or_default();
+
+    if let Some(fee_bips) = FEE_OVERRIDES.may_load(storage, recipient)? {
+        return Ok((fee_bips, volume, true));
+    }
+
+    let fee_bips = fee_config.tiers.iter()
+        .rev()
+        .find(|tier| volume >= tier.min_volume)
+        .map_or(fee_config.default_bips, |tier| tier.fee_bips);
+
+    Ok((fee_bips, volume, false))
+}
+
+fn try_set_gas_config(
+    deps: DepsMut,
+    state: State,
+    info: MessageInfo,
+    gas_price: Decimal,
+    max_gas: Uint128
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+
+    GAS_CONFIG.save(deps.storage, &GasConfig { gas_price, max_gas })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_gas_config")
+        .add_attribute("gas_price", gas_price.to_string())
+        .add_attribute("max_gas", max_gas)
+    )
+}
+
+fn try_claim_gas_reimbursement(
     deps: DepsMut,
-    state: &mut State,
     info: MessageInfo,
     env: Env,
-    juno_amount: Uint128,
-    token_amount_per_native: Uint128,
-    slippage_bips: Uint128,
-    recipient: Addr,
-    pool: Addr,
-    platform_fee_bips: Uint128,
-    gas_estimate: Uint128,
-    deadline: Uint64,
 ) -> Result<Response, ContractError> {
-    
-    if !BOT_ROLES.has(deps.storage, info.sender.clone()) {
-        return Err(ContractError::Unauthorized {});    
+    // a disabled bot keeps what it accrued while it was enabled
+    let mut ledger = GAS_LEDGERS.may_load(deps.storage, info.sender.clone())?.unwrap_or_default();
+    let claimable = ledger.accrued - ledger.claimed;
+    if claimable == Uint128::zero() {
+        return Err(ContractError::NoGasReimbursement {});
     }
-    let enabled = BOT_ROLES.load(deps.storage, info.sender)?;
-    if !enabled {
-        return Err(ContractError::UnauthorizedRole {});    
+    if get_shared_juno_balance(deps.querier, deps.storage, &env)? < claimable {
+        return Err(ContractError::InsufficientToken {});
     }
 
-    if env.block.time.seconds() > deadline.u64() {
-        return Err(ContractError::Expired { });
+    ledger.claimed += claimable;
+    GAS_LEDGERS.save(deps.storage, info.sender.clone(), &ledger)?;
+    PENDING_GAS_REIMBURSEMENT.update(deps.storage, |pending| -> StdResult<_> { Ok(pending - claimable) })?;
+
+    let msg = util::transfer_token_message(Denom::Native(String::from("ujuno")), claimable, info.sender.clone())?;
+
+    Ok(Response::new()
+        .add_message(msg)
+        .add_attribute("action", "claim_gas_reimbursement")
+        .add_attribute("bot", info.sender)
+        .add_attribute("amount", claimable)
+    )
+}
+
+fn try_register_pool(
+    deps: DepsMut,
+    state: State,
+    info: MessageInfo,
+    pool_address: Addr,
+    venue: Venue
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
     }
 
-    if slippage_bips > Uint128::from(10000u128) {
-        return Err(ContractError::BuyingUtilityOverSlippages { });
+    let juno_denom = Denom::Native(String::from("ujuno"));
+    let (token1_denom, token2_denom) = venue::get_venue(&venue).pool_denoms(deps.querier, pool_address.clone())?;
+    if token1_denom != juno_denom && token2_denom != juno_denom {
+        return Err(ContractError::PoolAndTokenMismatch { });
     }
 
-    if gas_estimate > juno_amount {
-        return Err(ContractError::InsufficientToken{});
+    let pool = PoolInfo {
+        token1_denom,
+        token2_denom,
+        enabled: true,
+        venue: venue.clone(),
+    };
+    POOLS.save(deps.storage, pool_address.clone(), &pool)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_pool")
+        .add_attribute("pool", pool_address)
+        .add_attribute("venue", venue.to_string())
+    )
+}
+
+fn try_set_pool_enabled(
+    deps: DepsMut,
+    state: State,
+    info: MessageInfo,
+    pool_address: Addr,
+    enabled: bool
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
     }
 
-    let mut messages = get_message_swap_atom(deps.querier
-                         , env,  String::from(ATOM_DENOM), Addr::unchecked(ATOM_JUNO_POOL_ADDR))?;
+    POOLS.update(deps.storage, pool_address.clone(), |pool| -> Result<_, ContractError> {
+        let mut pool = pool.ok_or(ContractError::PoolNotRegistered { })?;
+        pool.enabled = enabled;
+        Ok(pool)
+    })?;
 
-    let mut _juno_amount = juno_amount - gas_estimate;
+    Ok(Response::new()
+        .add_attribute("action", "set_pool_enabled")
+        .add_attribute("pool", pool_address)
+        .add_attribute("enabled", enabled.to_string())
+    )
+}
 
-    let platform_fee = platform_fee_bips * juno_amount / Uint128::from(10000u128);
-    state.pending_platform_fee += platform_fee;
-    //let approxTxFee = gas_estimate * tx.gasprice;
-    let amount_out_min = _juno_amount * token_amount_per_native * (Uint128::from(10000u128) - slippage_bips) / Uint128::from(10000000000u128);
-    _juno_amount -= platform_fee;
+/// Loads a registered pool, failing unless it is enabled and pairs ujuno
+fn load_enabled_pool(
+    storage: &dyn Storage,
+    pool_address: Addr
+) -> Result<PoolInfo, ContractError> {
+    let pool = POOLS.may_load(storage, pool_address)?
+        .ok_or(ContractError::PoolNotRegistered { })?;
+    if !pool.enabled {
+        return Err(ContractError::PoolDisabled { });
+    }
 
-    if _juno_amount <= Uint128::zero() {
-        return Err(ContractError::InsufficientEthToSwap{});
+    let juno_denom = Denom::Native(String::from("ujuno"));
+    if pool.token1_denom != juno_denom && pool.token2_denom != juno_denom {
+        return Err(ContractError::PoolAndTokenMismatch { });
+    }
+    Ok(pool)
+}
+
+/// ujuno is always accepted, a CW20 only if it is one side of a registered pool
+fn check_deposit_denom(
+    storage: &dyn Storage,
+    denom: &Denom,
+) -> Result<(), ContractError> {
+    if *denom == Denom::Native(String::from("ujuno")) {
+        return Ok(());
     }
+    if let Denom::Cw20(_) = denom {
+        for item in POOLS.range(storage, None, None, Order::Ascending) {
+            let (_, pool) = item?;
+            if pool.token1_denom == *denom || pool.token2_denom == *denom {
+                return Ok(());
+            }
+        }
+    }
+    Err(ContractError::UnsupportedDenom { })
+}
 
-    let (_token2_amount, _token2_denom, mut messages_swap) = 
-        util::get_swap_amount_and_denom_and_message(deps.querier
-            , pool
-            , Denom::Native(String::from("ujuno"))
-            , juno_amount
-            , amount_out_min
-            , Some(recipient))?;
-    messages.append(&mut messages_swap);    
+fn credit_user(
+    storage: &mut dyn Storage,
+    user: &Addr,
+    denom: &Denom,
+    amount: Uint128,
+) -> StdResult<Uint128> {
+    let key = denom_key(denom);
+    let mut balance = USER_BALANCES.may_load(storage, (user.clone(), key.clone()))?
+        .unwrap_or(DenomTotal { denom: denom.clone(), amount: Uint128::zero() });
+    balance.amount += amount;
+    USER_BALANCES.save(storage, (user.clone(), key.clone()), &balance)?;
 
-    config(deps.storage).save(&state)?;
+    let total = USER_BALANCE_TOTALS.may_load(storage, &key)?.unwrap_or_default();
+    USER_BALANCE_TOTALS.save(storage, &key, &(total + amount))?;
+    Ok(balance.amount)
+}
+
+fn debit_user(
+    storage: &mut dyn Storage,
+    user: &Addr,
+    denom: &Denom,
+    amount: Uint128,
+) -> Result<Uint128, ContractError> {
+    let key = denom_key(denom);
+    let mut balance = USER_BALANCES.may_load(storage, (user.clone(), key.clone()))?
+        .ok_or(ContractError::InsufficientUserBalance { })?;
+    balance.amount = balance.amount.checked_sub(amount)
+        .map_err(|_| ContractError::InsufficientUserBalance { })?;
+    if balance.amount.is_zero() {
+        USER_BALANCES.remove(storage, (user.clone(), key.clone()));
+    } else {
+        USER_BALANCES.save(storage, (user.clone(), key.clone()), &balance)?;
+    }
+
+    let total = USER_BALANCE_TOTALS.load(storage, &key)?;
+    USER_BALANCE_TOTALS.save(storage, &key, &(total - amount))?;
+    Ok(balance.amount)
+}
+
+/// The contract's ujuno that is not owed to users
+fn get_shared_juno_balance(
+    querier: QuerierWrapper,
+    storage: &dyn Storage,
+    env: &Env,
+) -> StdResult<Uint128> {
+    let juno_balance = util::get_token_amount(querier, Denom::Native(String::from("ujuno")), env.contract.address.clone())?;
+    let user_deposits = USER_BALANCE_TOTALS.may_load(storage, &denom_key(&Denom::Native(String::from("ujuno"))))?.unwrap_or_default();
+    Ok(juno_balance.saturating_sub(user_deposits))
+}
+
+fn try_deposit(
+    deps: DepsMut,
+    user: Addr,
+    denom: Denom,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    check_deposit_denom(deps.storage, &denom)?;
+
+    let balance = credit_user(deps.storage, &user, &denom, amount)?;
 
     Ok(Response::new()
-        .add_messages(messages))
+        .add_attribute("action", "deposit")
+        .add_attribute("user", user)
+        .add_attribute("denom", events::denom_attribute(&denom))
+        .add_attribute("amount", amount)
+        .add_attribute("balance", balance)
+    )
 }
 
-#[entry_point]
-pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+fn try_receive(
+    deps: DepsMut,
+    state: &mut State,
+    info: MessageInfo,
+    env: Env,
+    wrapper: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let msg: ReceiveMsg = from_binary(&wrapper.msg)?;
+    let user = validate_address(deps.api, &wrapper.sender, "sender")?;
+    if wrapper.amount.is_zero() {
+        return Err(ContractError::Cw20InputZero { });
+    }
+
     match msg {
-        QueryMsg::GetInfos {token} => to_binary(&query_infos(deps, env, token)?),
+        ReceiveMsg::Deposit {} => try_deposit(deps, user, Denom::Cw20(info.sender), wrapper.amount),
+        ReceiveMsg::RedeemPoints {} => try_redeem_points(deps, state, info, env, user, wrapper.amount),
     }
 }
 
-fn query_infos(deps: Deps, env: Env, token: String) -> StdResult<AdminResponse> {
-    let state = config_read(deps.storage).load()?;
-    let admin = state.owner;
-    let pending_platform_fee = state.pending_platform_fee;
-    let blocktime = env.block.time.seconds();
-    let contract_address = env.contract.address.clone();
-    let token_balance = util::get_token_amount(deps.querier, Denom::Native(token), env.contract.address.clone())?;
-    let token_balances = util::get_tokens_amounts(deps.querier, env.contract.address)?;
-    
-    Ok(AdminResponse { admin, pending_platform_fee, blocktime, token_balance, token_balances, contract_address })
+fn try_withdraw(
+    deps: DepsMut,
+    info: MessageInfo,
+    denom: Denom,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let balance = debit_user(deps.storage, &info.sender, &denom, amount)?;
+
+    Ok(Response::new()
+        .add_message(util::transfer_token_message(denom.clone(), amount, info.sender.clone())?)
+        .add_attribute("action", "withdraw")
+        .add_attribute("user", info.sender)
+        .add_attribute("denom", events::denom_attribute(&denom))
+        .add_attribute("amount", amount)
+        .add_attribute("balance", balance)
+    )
+}
+
+fn try_set_intent_key(
+    deps: DepsMut,
+    info: MessageInfo,
+    pubkey: Binary,
+) -> Result<Response, ContractError> {
+    INTENT_KEYS.save(deps.storage, info.sender.clone(), &pubkey)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_intent_key")
+        .add_attribute("user", info.sender)
+        .add_attribute("pubkey", pubkey.to_base64())
+    )
+}
+
+/// Checks a signed intent against the buy without burning its nonce, returning the user whose deposit pays
+fn verify_buy_intent(
+    api: &dyn Api,
+    storage: &dyn Storage,
+    env: &Env,
+    signed: &SignedBuyIntent,
+    params: &BuyParams,
+    pool: &Addr,
+    amounts: &BuyAmounts,
+) -> Result<Addr, ContractError> {
+    let intent = &signed.intent;
+    let intent_user = validate_address(api, &intent.user, "intent.user")?;
+    let intent_recipient = validate_address(api, &intent.recipient, "intent.recipient")?;
+    let intent_pool = validate_address(api, &intent.pool_address, "intent.pool_address")?;
+
+    if params.recipient != intent_recipient {
+        return Err(ContractError::InvalidRecipient { });
+    }
+    if env.block.time.seconds() > intent.expiry.u64() {
+        return Err(ContractError::Expired { });
+    }
+    if *pool != intent_pool
+        || amounts.juno_amount > intent.max_juno_amount
+        || params.token_amount_per_native < intent.min_token_amount_per_native
+        || params.slippage_bips > intent.max_slippage_bips
+        || amounts.platform_fee_bips > intent.max_platform_fee_bips
+        || amounts.gas_reimbursement > intent.max_gas_reimbursement {
+        return Err(ContractError::IntentViolated { });
+    }
+    if INTENT_NONCES.has(storage, (intent_user.clone(), intent.nonce)) {
+        return Err(ContractError::IntentReplayed { });
+    }
+
+    let pubkey = INTENT_KEYS.may_load(storage, intent_user.clone())?
+        .ok_or(ContractError::IntentKeyNotSet { })?;
+    let hash = util::intent_hash(&env.contract.address, intent)?;
+    let valid = api.secp256k1_verify(&hash, &signed.signature, &pubkey)
+        .map_err(|_| ContractError::InvalidSignature { })?;
+    if !valid {
+        return Err(ContractError::InvalidSignature { });
+    }
+    Ok(intent_user)
+}
+
+fn try_withdraw_fee(
+    deps: DepsMut,
+    state: &mut State,
+    info: MessageInfo,
+    env: Env,
+    to: Addr,
+    amount: Uint128
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+
+    // the ledger may only be drawn down to zero, and the ujuno actually held must cover
+    // the withdrawal on top of what is still owed to bots for gas and to users
+    state.pending_platform_fee = state.pending_platform_fee.checked_sub(amount)
+        .map_err(|_| ContractError::InsufficientFeeBalance { })?;
+
+    let juno_balance = get_shared_juno_balance(deps.querier, deps.storage, &env)?;
+    let pending_gas = PENDING_GAS_REIMBURSEMENT.may_load(deps.storage)?.unwrap_or_default();
+    if juno_balance.saturating_sub(pending_gas) < amount {
+        return Err(ContractError::InsufficientFeeBalance { });
+    }
+
+    config(deps.storage).save(&state)?;
+    add_to_denom_total(deps.storage, &FEES_WITHDRAWN_BY_DENOM, &Denom::Native(String::from("ujuno")), amount)?;
+
+    let mut msgs: Vec<CosmosMsg> = vec![];
+
+    msgs.push(util::transfer_token_message(Denom::Native(String::from("ujuno")), amount, to.clone())?);
+
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "withdraw_fee")
+        .add_attribute("to", to.clone())
+        .add_attribute("denom", "ujuno")
+        .add_attribute("amount", amount)
+        .add_attribute("pending_platform_fee", state.pending_platform_fee)
+        .add_event(events::fee_withdraw_event(&to, &Denom::Native(String::from("ujuno")), amount, state.pending_platform_fee))
+    )
+}
+
+fn try_set_buyback_config(
+    deps: DepsMut,
+    state: State,
+    info: MessageInfo,
+    pool: Addr,
+    max_slippage_bips: Uint128,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+    if max_slippage_bips > Uint128::from(MAX_BIPS) {
+        return Err(ContractError::BuyingUtilityOverSlippages { });
+    }
+
+    let pool_info_response = util::get_pool_info(deps.querier, pool.clone())?;
+    let token = match (pool_info_response.token1_denom, pool_info_response.token2_denom) {
+        (Denom::Native(native_str), Denom::Cw20(token)) | (Denom::Cw20(token), Denom::Native(native_str)) if native_str == "ujuno" => token,
+        _ => return Err(ContractError::PoolAndTokenMismatch { }),
+    };
+
+    BUYBACK_CONFIG.save(deps.storage, &BuybackConfig { pool: pool.clone(), token: token.clone(), max_slippage_bips })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_buyback_config")
+        .add_attribute("pool", pool)
+        .add_attribute("token", token)
+        .add_attribute("max_slippage_bips", max_slippage_bips)
+    )
 }
 
+/// Swaps fees into the buyback token, the reply burns what arrived
+fn try_buyback_and_burn(
+    deps: DepsMut,
+    state: &mut State,
+    info: MessageInfo,
+    env: Env,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender && !BOT_ROLES.may_load(deps.storage, info.sender.clone())?.unwrap_or(false) {
+        return Err(ContractError::Unauthorized { });
+    }
+    let buyback = BUYBACK_CONFIG.may_load(deps.storage)?.ok_or(ContractError::BuybackNotConfigured { })?;
+
+    // same bounds as WithdrawFee
+    state.pending_platform_fee = state.pending_platform_fee.checked_sub(amount)
+        .map_err(|_| ContractError::InsufficientFeeBalance { })?;
+    let juno_balance = get_shared_juno_balance(deps.querier, deps.storage, &env)?;
+    let pending_gas = PENDING_GAS_REIMBURSEMENT.may_load(deps.storage)?.unwrap_or_default();
+    if juno_balance.saturating_sub(pending_gas) < amount {
+        return Err(ContractError::InsufficientFeeBalance { });
+    }
+
+    let juno_denom = Denom::Native(String::from("ujuno"));
+    let pool = util::get_wasmswap_pool(deps.querier, buyback.pool.clone())?;
+    let (token_amount, token_denom, input_token) = util::get_swap_amount_and_denom(deps.querier, buyback.pool.clone(), &pool, juno_denom.clone(), amount)?;
+    let amount_out_min = token_amount.multiply_ratio(Uint128::from(MAX_BIPS) - buyback.max_slippage_bips, MAX_BIPS);
+
+    // the pool may pay out anything down to the configured slippage below the quote
+    let messages = util::swap_token_messages(juno_denom.clone(), input_token, amount, amount_out_min, buyback.pool.clone(), None)?;
+
+    config(deps.storage).save(&state)?;
+    add_to_denom_total(deps.storage, &FEES_WITHDRAWN_BY_DENOM, &juno_denom, amount)?;
+    let trade = save_trade(deps.storage, Trade {
+        id: 0,
+        bot: info.sender.clone(),
+        recipient: env.contract.address.clone(),
+        pool: buyback.pool,
+        block_height: env.block.height,
+        block_time: env.block.time.seconds(),
+        input_denom: juno_denom,
+        input_amount: amount,
+        platform_fee: Uint128::zero(),
+        gas_reimbursement: Uint128::zero(),
+        quoted_output: token_amount,
+        amount_out_min,
+        output_denom: token_denom.clone(),
+    })?;
+    PENDING_BURN.save(deps.storage, &util::get_token_amount(deps.querier, token_denom, env.contract.address)?)?;
+
+    Ok(Response::new()
+        .add_submessages(reply_on_last(messages, REPLY_BUYBACK))
+        .add_attribute("action", "buyback_and_burn")
+        .add_attribute("trade_id", trade.id.to_string())
+        .add_attribute("amount", amount)
+        .add_attribute("quoted_amount", token_amount)
+        .add_attribute("pending_platform_fee", state.pending_platform_fee)
+    )
+}
+
+fn try_set_liquidity_config(
+    deps: DepsMut,
+    state: State,
+    info: MessageInfo,
+    pool: Addr,
+    max_slippage_bips: Uint128,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+    if max_slippage_bips > Uint128::from(MAX_BIPS) {
+        return Err(ContractError::BuyingUtilityOverSlippages { });
+    }
+
+    let pool_info_response = util::get_pool_info(deps.querier, pool.clone())?;
+    let juno_denom = Denom::Native(String::from("ujuno"));
+    if pool_info_response.token1_denom != juno_denom && pool_info_response.token2_denom != juno_denom {
+        return Err(ContractError::PoolAndTokenMismatch { });
+    }
+    let lp_token = deps.api.addr_validate(&pool_info_response.lp_token_address)?;
+
+    LIQUIDITY_CONFIG.save(deps.storage, &LiquidityConfig { pool: pool.clone(), lp_token: lp_token.clone(), max_slippage_bips })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_liquidity_config")
+        .add_attribute("pool", pool)
+        .add_attribute("lp_token", lp_token)
+        .add_attribute("max_slippage_bips", max_slippage_bips)
+    )
+}
+
+/// Swaps half of `amount` fees to the pool's other token, the reply adds what arrived and the other
+/// half to the pool
+fn try_compound_fees_to_liquidity(
+    deps: DepsMut,
+    state: &mut State,
+    info: MessageInfo,
+    env: Env,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+    let liquidity = LIQUIDITY_CONFIG.may_load(deps.storage)?.ok_or(ContractError::LiquidityNotConfigured { })?;
+
+    // same bounds as WithdrawFee
+    state.pending_platform_fee = state.pending_platform_fee.checked_sub(amount)
+        .map_err(|_| ContractError::InsufficientFeeBalance { })?;
+    let juno_balance = get_shared_juno_balance(deps.querier, deps.storage, &env)?;
+    let pending_gas = PENDING_GAS_REIMBURSEMENT.may_load(deps.storage)?.unwrap_or_default();
+    if juno_balance.saturating_sub(pending_gas) < amount {
+        return Err(ContractError::InsufficientFeeBalance { });
+    }
+
+    let juno_denom = Denom::Native(String::from("ujuno"));
+    let pool_info_response = util::get_pool_info(deps.querier, liquidity.pool.clone())?;
+    if pool_info_response.lp_token_supply.is_zero() {
+        return Err(ContractError::InsufficientToken { });
+    }
+
+    let pool = PoolInfo {
+        token1_denom: pool_info_response.token1_denom.clone(),
+        token2_denom: pool_info_response.token2_denom.clone(),
+        enabled: true,
+        venue: Venue::Wasmswap,
+    };
+
+    let swap_amount = amount / Uint128::from(2u128);
+    let (token_amount, token_denom, input_token) = util::get_swap_amount_and_denom(deps.querier, liquidity.pool.clone(), &pool, juno_denom.clone(), swap_amount)?;
+    let amount_out_min = token_amount.multiply_ratio(Uint128::from(MAX_BIPS) - liquidity.max_slippage_bips, MAX_BIPS);
+
+    // the pool may pay out anything down to the configured slippage below the quote
+    let messages = util::swap_token_messages(juno_denom, input_token, swap_amount, amount_out_min, liquidity.pool.clone(), None)?;
+
+    config(deps.storage).save(&state)?;
+    PENDING_COMPOUND.save(deps.storage, &PendingCompound {
+        amount,
+        juno_left: amount - swap_amount,
+        token_denom: token_denom.clone(),
+        balance_before: util::get_token_amount(deps.querier, token_denom, env.contract.address)?,
+    })?;
+
+    Ok(Response::new()
+        .add_submessages(reply_on_last(messages, REPLY_COMPOUND))
+        .add_attribute("action", "compound_fees_to_liquidity")
+        .add_attribute("pool", liquidity.pool)
+        .add_attribute("amount", amount)
+        .add_attribute("quoted_amount", token_amount)
+        .add_attribute("amount_out_min", amount_out_min)
+        .add_attribute("pending_platform_fee", state.pending_platform_fee)
+    )
+}
+
+/// Withdraws from the configured pool. The pool pays the contract, the reply passes what arrived on to `to`
+fn try_remove_liquidity(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    amount: Uint128,
+    min_token1: Uint128,
+    min_token2: Uint128,
+    to: Addr,
+) -> Result<Response, ContractError> {
+    let state = config_read(deps.storage).load()?;
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+    let liquidity = LIQUIDITY_CONFIG.may_load(deps.storage)?.ok_or(ContractError::LiquidityNotConfigured { })?;
+
+    let lp_balance = util::get_token_amount(deps.querier, Denom::Cw20(liquidity.lp_token.clone()), env.contract.address.clone())?;
+    if amount.is_zero() || amount > lp_balance {
+        return Err(ContractError::InsufficientToken { });
+    }
+
+    let pool_info_response = util::get_pool_info(deps.querier, liquidity.pool.clone())?;
+    PENDING_REMOVE_LIQUIDITY.save(deps.storage, &PendingRemoveLiquidity {
+        to: to.clone(),
+        token1_balance_before: util::get_token_amount(deps.querier, pool_info_response.token1_denom.clone(), env.contract.address.clone())?,
+        token1_denom: pool_info_response.token1_denom,
+        token2_balance_before: util::get_token_amount(deps.querier, pool_info_response.token2_denom.clone(), env.contract.address)?,
+        token2_denom: pool_info_response.token2_denom,
+    })?;
+
+    let messages = util::remove_liquidity_messages(liquidity.pool.clone(), liquidity.lp_token, amount, min_token1, min_token2)?;
+
+    Ok(Response::new()
+        .add_submessages(reply_on_last(messages, REPLY_REMOVE_LIQUIDITY))
+        .add_attribute("action", "remove_liquidity")
+        .add_attribute("pool", liquidity.pool)
+        .add_attribute("amount", amount)
+        .add_attribute("to", to)
+    )
+}
+
+fn try_set_loyalty_config(
+    deps: DepsMut,
+    state: State,
+    info: MessageInfo,
+    env: Env,
+    loyalty: LoyaltyConfig,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+    if loyalty.epoch_seconds == 0 {
+        return Err(ContractError::InvalidLoyaltyConfig { });
+    }
+    // a buy's points may not be worth more than the highest fee it can pay
+    let max_fee = FEE_CONFIG.load(deps.storage)?.max_bips;
+    if loyalty.points_per_ujuno * loyalty.ujuno_per_point > Decimal::from_ratio(max_fee, MAX_BIPS) {
+        return Err(ContractError::InvalidLoyaltyConfig { });
+    }
+
+    let minter = util::get_cw20_minter(deps.querier, loyalty.token.clone())?;
+    if minter.as_deref() != Some(env.contract.address.as_str()) {
+        return Err(ContractError::ContractNotMinter { });
+    }
+
+    LOYALTY_CONFIG.save(deps.storage, &loyalty)?;
+    LOYALTY_EPOCH.remove(deps.storage);
+
+    Ok(Response::new()
+        .add_attribute("action", "set_loyalty_config")
+        .add_attribute("token", loyalty.token)
+        .add_attribute("points_per_ujuno", loyalty.points_per_ujuno.to_string())
+        .add_attribute("ujuno_per_point", loyalty.ujuno_per_point.to_string())
+        .add_attribute("epoch_seconds", loyalty.epoch_seconds.to_string())
+        .add_attribute("epoch_cap", loyalty.epoch_cap)
+    )
+}
+
+/// Returns the current epoch and what was minted in it so far
+fn load_loyalty_epoch(
+    storage: &dyn Storage,
+    env: &Env,
+    loyalty: &LoyaltyConfig,
+) -> StdResult<LoyaltyEpoch> {
+    let epoch = env.block.time.seconds() / loyalty.epoch_seconds;
+    Ok(LOYALTY_EPOCH.may_load(storage)?
+        .filter(|current| current.epoch == epoch)
+        .unwrap_or(LoyaltyEpoch { epoch, minted: Uint128::zero() }))
+}
+
+/// Mints points for the ujuno spent, as far as the epoch cap still allows and never worth more than the platform fee paid
+fn mint_loyalty_points(
+    storage: &mut dyn Storage,
+    env: &Env,
+    recipient: &Addr,
+    amounts: &BuyAmounts,
+) -> StdResult<(Vec<CosmosMsg>, Uint128)> {
+    let loyalty = match LOYALTY_CONFIG.may_load(storage)? {
+        Some(loyalty) => loyalty,
+        None => return Ok((vec![], Uint128::zero())),
+    };
+
+    let mut epoch = load_loyalty_epoch(storage, env, &loyalty)?;
+    let mut points = std::cmp::min(amounts.juno_amount * loyalty.points_per_ujuno, loyalty.epoch_cap.saturating_sub(epoch.minted));
+    if !loyalty.ujuno_per_point.is_zero() {
+        points = std::cmp::min(points, amounts.platform_fee.multiply_ratio(Decimal::one().atomics(), loyalty.ujuno_per_point.atomics()));
+    }
+    if points.is_zero() {
+        return Ok((vec![], points));
+    }
+    epoch.minted += points;
+    LOYALTY_EPOCH.save(storage, &epoch)?;
+
+    let mint: CosmosMsg = WasmMsg::Execute {
+        contract_addr: loyalty.token.into(),
+        msg: to_binary(&Cw20ExecuteMsg::Mint { recipient: recipient.into(), amount: points })?,
+        funds: vec![],
+    }.into();
+    Ok((vec![mint], points))
+}
+
+/// Burns the points and pays their ujuno value out of the platform fee ledger
+fn try_redeem_points(
+    deps: DepsMut,
+    state: &mut State,
+    info: MessageInfo,
+    env: Env,
+    user: Addr,
+    points: Uint128,
+) -> Result<Response, ContractError> {
+    let loyalty = LOYALTY_CONFIG.may_load(deps.storage)?.ok_or(ContractError::LoyaltyNotConfigured { })?;
+    if info.sender != loyalty.token {
+        return Err(ContractError::UnsupportedDenom { });
+    }
+
+    let rebate = points * loyalty.ujuno_per_point;
+    state.pending_platform_fee = state.pending_platform_fee.checked_sub(rebate)
+        .map_err(|_| ContractError::InsufficientFeeBalance { })?;
+
+    let juno_balance = get_shared_juno_balance(deps.querier, deps.storage, &env)?;
+    let pending_gas = PENDING_GAS_REIMBURSEMENT.may_load(deps.storage)?.unwrap_or_default();
+    if juno_balance.saturating_sub(pending_gas) < rebate {
+        return Err(ContractError::InsufficientFeeBalance { });
+    }
+
+    config(deps.storage).save(&state)?;
+    add_to_denom_total(deps.storage, &FEES_WITHDRAWN_BY_DENOM, &Denom::Native(String::from("ujuno")), rebate)?;
+
+    let mut msgs: Vec<CosmosMsg> = vec![WasmMsg::Execute {
+        contract_addr: loyalty.token.into(),
+        msg: to_binary(&Cw20ExecuteMsg::Burn { amount: points })?,
+        funds: vec![],
+    }.into()];
+    if !rebate.is_zero() {
+        msgs.push(util::transfer_token_message(Denom::Native(String::from("ujuno")), rebate, user.clone())?);
+    }
+
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "redeem_points")
+        .add_attribute("user", user)
+        .add_attribute("points", points)
+        .add_attribute("rebate", rebate)
+        .add_attribute("pending_platform_fee", state.pending_platform_fee)
+    )
+}
+
+fn try_set_liquid_reserve(
+    deps: DepsMut,
+    state: State,
+    info: MessageInfo,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+
+    LIQUID_RESERVE.save(deps.storage, &amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_liquid_reserve")
+        .add_attribute("amount", amount)
+    )
+}
+
+fn check_validator(querier: QuerierWrapper, validator: &str) -> Result<(), ContractError> {
+    querier.query_validator(validator)?.ok_or(ContractError::UnknownValidator { })?;
+    Ok(())
+}
+
+/// Stake can still be moved off a validator that left the active set, so only the delegation is checked
+fn check_delegation(querier: QuerierWrapper, env: &Env, validator: &str) -> Result<(), ContractError> {
+    querier.query_delegation(env.contract.address.clone(), validator)?.ok_or(ContractError::NoDelegation { })?;
+    Ok(())
+}
+
+/// Rewards accrued with `validator`, which the staking module withdraws to the contract on any change
+/// to the delegation. Zero if the contract does not delegate to it.
+fn query_staking_rewards(querier: QuerierWrapper, env: &Env, validator: &str) -> StdResult<Uint128> {
+    Ok(querier.query_delegation(env.contract.address.clone(), validator)?
+        .map_or(Uint128::zero(), |delegation| get_juno_rewards(&delegation.accumulated_rewards)))
+}
+
+/// Books withdrawn staking rewards into the fee ledger
+fn book_staking_rewards(storage: &mut dyn Storage, state: &mut State, rewards: Uint128) -> StdResult<()> {
+    state.pending_platform_fee += rewards;
+    config(storage).save(state)?;
+    add_to_denom_total(storage, &FEES_ACCRUED_BY_DENOM, &Denom::Native(String::from("ujuno")), rewards)
+}
+
+/// Stakes the contract's own ujuno, leaving the liquid reserve and the gas owed to bots untouched
+fn try_delegate(
+    deps: DepsMut,
+    state: &mut State,
+    info: MessageInfo,
+    env: Env,
+    validator: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+    if amount.is_zero() {
+        return Err(ContractError::NativeInputZero { });
+    }
+    check_validator(deps.querier, &validator)?;
+
+    let liquid_balance = get_shared_juno_balance(deps.querier, deps.storage, &env)?;
+    let reserve = LIQUID_RESERVE.may_load(deps.storage)?.unwrap_or_default();
+    let pending_gas = PENDING_GAS_REIMBURSEMENT.may_load(deps.storage)?.unwrap_or_default();
+    if liquid_balance.saturating_sub(amount) < reserve + pending_gas || liquid_balance < amount {
+        return Err(ContractError::LiquidReserveBreached { });
+    }
+
+    let rewards = query_staking_rewards(deps.querier, &env, &validator)?;
+    book_staking_rewards(deps.storage, state, rewards)?;
+
+    Ok(Response::new()
+        .add_message(StakingMsg::Delegate { validator: validator.clone(), amount: Coin { denom: String::from("ujuno"), amount } })
+        .add_attribute("action", "delegate")
+        .add_attribute("validator", validator)
+        .add_attribute("amount", amount)
+        .add_attribute("rewards", rewards)
+        .add_attribute("pending_platform_fee", state.pending_platform_fee)
+    )
+}
+
+fn try_undelegate(
+    deps: DepsMut,
+    state: &mut State,
+    info: MessageInfo,
+    env: Env,
+    validator: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+    if amount.is_zero() {
+        return Err(ContractError::NativeInputZero { });
+    }
+    check_delegation(deps.querier, &env, &validator)?;
+
+    let rewards = query_staking_rewards(deps.querier, &env, &validator)?;
+    book_staking_rewards(deps.storage, state, rewards)?;
+
+    Ok(Response::new()
+        .add_message(StakingMsg::Undelegate { validator: validator.clone(), amount: Coin { denom: String::from("ujuno"), amount } })
+        .add_attribute("action", "undelegate")
+        .add_attribute("validator", validator)
+        .add_attribute("amount", amount)
+        .add_attribute("rewards", rewards)
+        .add_attribute("pending_platform_fee", state.pending_platform_fee)
+    )
+}
+
+/// Both validators pay out their rewards when the stake moves
+fn try_redelegate(
+    deps: DepsMut,
+    state: &mut State,
+    info: MessageInfo,
+    env: Env,
+    src_validator: String,
+    dst_validator: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+    if amount.is_zero() {
+        return Err(ContractError::NativeInputZero { });
+    }
+    check_delegation(deps.querier, &env, &src_validator)?;
+    check_validator(deps.querier, &dst_validator)?;
+
+    let rewards = query_staking_rewards(deps.querier, &env, &src_validator)?
+        + query_staking_rewards(deps.querier, &env, &dst_validator)?;
+    book_staking_rewards(deps.storage, state, rewards)?;
+
+    Ok(Response::new()
+        .add_message(StakingMsg::Redelegate {
+            src_validator: src_validator.clone(),
+            dst_validator: dst_validator.clone(),
+            amount: Coin { denom: String::from("ujuno"), amount },
+        })
+        .add_attribute("action", "redelegate")
+        .add_attribute("src_validator", src_validator)
+        .add_attribute("dst_validator", dst_validator)
+        .add_attribute("amount", amount)
+        .add_attribute("rewards", rewards)
+        .add_attribute("pending_platform_fee", state.pending_platform_fee)
+    )
+}
+
+/// Rewards are paid to the contract and booked into the fee ledger, so WithdrawFee can pay them out
+fn try_claim_staking_rewards(
+    deps: DepsMut,
+    state: &mut State,
+    info: MessageInfo,
+    env: Env,
+    validator: Option<String>,
+) -> Result<Response, ContractError> {
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized { });
+    }
+
+    let validators = match validator {
+        Some(validator) => vec![validator],
+        None => deps.querier.query_all_delegations(env.contract.address.clone())?
+            .into_iter()
+            .map(|delegation| delegation.validator)
+            .collect(),
+    };
+
+    let mut response = Response::new()
+        .add_attribute("action", "claim_staking_rewards");
+    let mut rewards = Uint128::zero();
+    for validator in validators {
+        let delegation = deps.querier.query_delegation(env.contract.address.clone(), validator.clone())?
+            .ok_or(ContractError::UnknownValidator { })?;
+        rewards += get_juno_rewards(&delegation.accumulated_rewards);
+
+        response = response
+            .add_message(DistributionMsg::WithdrawDelegatorReward { validator: validator.clone() })
+            .add_attribute("validator", validator);
+    }
+
+    // withdrawals pay out whole coins, the fraction stays with the distribution module
+    book_staking_rewards(deps.storage, state, rewards)?;
+
+    Ok(response
+        .add_attribute("rewards", rewards)
+        .add_attribute("pending_platform_fee", state.pending_platform_fee)
+    )
+}
+
+fn get_juno_rewards(rewards: &[Coin]) -> Uint128 {
+    rewards.iter()
+        .filter(|coin| coin.denom == "ujuno")
+        .map(|coin| coin.amount)
+        .sum()
+}
+
+/// The terms a bot buys on: what it spends and for whom, its price floor and what it charges
+struct BuyParams {
+    juno_amount: Uint128,
+    token_amount_per_native: Uint128,
+    slippage_bips: Uint128,
+    recipient: Addr,
+    platform_fee_bips: Option<Uint128>,
+    gas_estimate: Uint128,
+    deadline: Uint64,
+}
+
+/// What a buy costs once the platform fee and gas reimbursement are taken out of `juno_amount`
+struct BuyAmounts {
+    juno_amount: Uint128,
+    platform_fee_bips: Uint128,
+    platform_fee: Uint128,
+    gas_reimbursement: Uint128,
+    swap_amount: Uint128,
+    amount_out_min: Uint128,
+    recipient_volume: Uint128,
+}
+
+fn check_buy_preconditions(
+    storage: &dyn Storage,
+    bot: &Addr,
+    env: &Env,
+    slippage_bips: Uint128,
+    deadline: Uint64,
+) -> Result<(), ContractError> {
+    check_bot_role(storage, bot)?;
+    check_buy_terms(env, slippage_bips, deadline)
+}
+
+fn check_bot_role(
+    storage: &dyn Storage,
+    bot: &Addr,
+) -> Result<(), ContractError> {
+    if !BOT_ROLES.has(storage, bot.clone()) {
+        return Err(ContractError::Unauthorized {});    
+    }
+    let enabled = BOT_ROLES.load(storage, bot.clone())?;
+    if !enabled {
+        return Err(ContractError::UnauthorizedRole {});    
+    }
+    Ok(())
+}
+
+fn check_buy_terms(
+    env: &Env,
+    slippage_bips: Uint128,
+    deadline: Uint64,
+) -> Result<(), ContractError> {
+    if env.block.time.seconds() > deadline.u64() {
+        return Err(ContractError::Expired { });
+    }
+
+    if slippage_bips > Uint128::from(MAX_BIPS) {
+        return Err(ContractError::BuyingUtilityOverSlippages { });
+    }
+    Ok(())
+}
+
+/// Checks bot-supplied bips against the recipient's discounted schedule, returning the bips to charge and the recipient's volume
+fn resolve_fee_bips(
+    querier: QuerierWrapper,
+    storage: &dyn Storage,
+    recipient: &Addr,
+    platform_fee_bips: Option<Uint128>,
+) -> Result<(Uint128, Uint128), ContractError> {
+    let fee_config = FEE_CONFIG.load(storage)?;
+    let (scheduled_fee_bips, volume, _overridden, discount_bips) = get_discounted_fee_bips(querier, storage, &fee_config, recipient)?;
+    let platform_fee_bips = platform_fee_bips.unwrap_or(scheduled_fee_bips);
+    if platform_fee_bips < scheduled_fee_bips || platform_fee_bips > fee_config.max_bips {
+        return Err(ContractError::PlatformFeeOutOfBounds { });
+    }
+    // a holder discount caps the fee at the discounted schedule, whatever the bot asks for
+    if !discount_bips.is_zero() {
+        return Ok((scheduled_fee_bips, volume));
+    }
+    Ok((platform_fee_bips, volume))
+}
+
+/// The bot is reimbursed its gas estimate, capped by the configured gas price times max gas
+fn resolve_gas_reimbursement(
+    storage: &dyn Storage,
+    gas_estimate: Uint128,
+) -> StdResult<Uint128> {
+    let gas_config = GAS_CONFIG.load(storage)?;
+    Ok(std::cmp::min(gas_estimate, gas_config.max_gas * gas_config.gas_price))
+}
+
+fn compute_buy_amounts(
+    querier: QuerierWrapper,
+    storage: &dyn Storage,
+    params: &BuyParams,
+) -> Result<BuyAmounts, ContractError> {
+    let juno_amount = params.juno_amount;
+    if params.gas_estimate > juno_amount {
+        return Err(ContractError::InsufficientToken{});
+    }
+
+    let (platform_fee_bips, volume) = resolve_fee_bips(querier, storage, &params.recipient, params.platform_fee_bips)?;
+    let gas_reimbursement = resolve_gas_reimbursement(storage, params.gas_estimate)?;
+
+    let mut _juno_amount = juno_amount - gas_reimbursement;
+
+    let platform_fee = platform_fee_bips * juno_amount / Uint128::from(MAX_BIPS);
+    //let approxTxFee = gas_estimate * tx.gasprice;
+    _juno_amount = _juno_amount.checked_sub(platform_fee).map_err(|_| ContractError::InsufficientEthToSwap{})?;
+
+    if _juno_amount <= Uint128::zero() {
+        return Err(ContractError::InsufficientEthToSwap{});
+    }
+    let amount_out_min = _juno_amount * params.token_amount_per_native * (Uint128::from(MAX_BIPS) - params.slippage_bips) / Uint128::from(10000000000u128);
+
+    Ok(BuyAmounts {
+        juno_amount,
+        platform_fee_bips,
+        platform_fee,
+        gas_reimbursement,
+        swap_amount: _juno_amount,
+        amount_out_min,
+        recipient_volume: volume,
+    })
+}
+
+fn add_to_denom_total(
+    storage: &mut dyn Storage,
+    totals: &Map<&str, DenomTotal>,
+    denom: &Denom,
+    amount: Uint128,
+) -> StdResult<()> {
+    let key = denom_key(denom);
+    let mut total = totals.may_load(storage, &key)?
+        .unwrap_or(DenomTotal { denom: denom.clone(), amount: Uint128::zero() });
+    total.amount += amount;
+    totals.save(storage, &key, &total)
+}
+
+/// Stores a trade under the next trade id, counts it into the stats and returns it with its id
+fn save_trade(
+    storage: &mut dyn Storage,
+    mut trade: Trade,
+) -> StdResult<Trade> {
+    let id = TRADE_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    TRADE_COUNT.save(storage, &id)?;
+
+    let mut stats = STATS.may_load(storage)?.unwrap_or_default();
+    stats.total_trades += 1;
+    STATS.save(storage, &stats)?;
+
+    add_to_denom_total(storage, &VOLUME_BY_DENOM, &trade.input_denom, trade.input_amount)?;
+    // the ATOM sweep runs through its pool too, so the volume is kept per input denom
+    let key = (trade.pool.clone(), denom_key(&trade.input_denom));
+    let mut pool_volume = POOL_VOLUMES.may_load(storage, key.clone())?
+        .unwrap_or(DenomTotal { denom: trade.input_denom.clone(), amount: Uint128::zero() });
+    pool_volume.amount += trade.input_amount;
+    POOL_VOLUMES.save(storage, key, &pool_volume)?;
+
+    trade.id = id;
+    trades().save(storage, id, &trade)?;
+    Ok(trade)
+}
+
+fn buy_trade(
+    env: &Env,
+    bot: &Addr,
+    recipient: &Addr,
+    pool: &Addr,
+    amounts: &BuyAmounts,
+    quoted_output: Uint128,
+    output_denom: Denom,
+) -> Trade {
+    Trade {
+        id: 0,
+        bot: bot.clone(),
+        recipient: recipient.clone(),
+        pool: pool.clone(),
+        block_height: env.block.height,
+        block_time: env.block.time.seconds(),
+        input_denom: Denom::Native(String::from("ujuno")),
+        input_amount: amounts.juno_amount,
+        platform_fee: amounts.platform_fee,
+        gas_reimbursement: amounts.gas_reimbursement,
+        quoted_output,
+        amount_out_min: amounts.amount_out_min,
+        output_denom,
+    }
+}
+
+fn check_shared_balance(
+    querier: QuerierWrapper,
+    storage: &dyn Storage,
+    env: &Env,
+    swap_amount: Uint128,
+) -> Result<(), ContractError> {
+    if get_shared_juno_balance(querier, storage, env)? < swap_amount {
+        return Err(ContractError::InsufficientToken { });
+    }
+    Ok(())
+}
+
+/// Books a buy into the fee ledger, the bot's gas ledger and the recipient's volume
+fn record_buy(
+    storage: &mut dyn Storage,
+    bot: &Addr,
+    recipient: &Addr,
+    amounts: &BuyAmounts,
+) -> StdResult<()> {
+    config(storage).update(|mut state| -> StdResult<_> {
+        state.pending_platform_fee += amounts.platform_fee;
+        Ok(state)
+    })?;
+    add_to_denom_total(storage, &FEES_ACCRUED_BY_DENOM, &Denom::Native(String::from("ujuno")), amounts.platform_fee)?;
+
+    if !RECIPIENT_VOLUMES.has(storage, recipient.clone()) {
+        let mut stats = STATS.may_load(storage)?.unwrap_or_default();
+        stats.unique_recipients += 1;
+        STATS.save(storage, &stats)?;
+    }
+
+    if amounts.gas_reimbursement > Uint128::zero() {
+        let mut ledger = GAS_LEDGERS.may_load(storage, bot.clone())?.unwrap_or_default();
+        ledger.accrued += amounts.gas_reimbursement;
+        GAS_LEDGERS.save(storage, bot.clone(), &ledger)?;
+        PENDING_GAS_REIMBURSEMENT.update(storage, |pending| -> StdResult<_> { Ok(pending + amounts.gas_reimbursement) })?;
+    }
+
+    RECIPIENT_VOLUMES.save(storage, recipient.clone(), &(amounts.recipient_volume + amounts.juno_amount))?;
+    Ok(())
+}
+
+/// Everything BuyToken checks before it writes, shared with SimulateBuy: the deadline and slippage, the pool,
+/// the fee and gas math, the intent and that the balance paying covers the buy. Returns the pool, the amounts
+/// and the user whose deposit pays, if any
+fn check_buy_token(
+    deps: Deps,
+    env: &Env,
+    params: &BuyParams,
+    pool: &Addr,
+    intent: Option<&SignedBuyIntent>,
+) -> Result<(PoolInfo, BuyAmounts, Option<Addr>), ContractError> {
+    check_buy_terms(env, params.slippage_bips, params.deadline)?;
+
+    let pool_info = load_enabled_pool(deps.storage, pool.clone())?;
+
+    let amounts = compute_buy_amounts(deps.querier, deps.storage, params)?;
+
+    // a user's deposit is only spent on a buy the user signed
+    let user = intent
+        .map(|signed| verify_buy_intent(deps.api, deps.storage, env, signed, params, pool, &amounts))
+        .transpose()?;
+
+    // shared buys must leave the deposits alone
+    match &user {
+        Some(user) => {
+            let balance = USER_BALANCES.may_load(deps.storage, (user.clone(), denom_key(&Denom::Native(String::from("ujuno")))))?
+                .map_or(Uint128::zero(), |balance| balance.amount);
+            if balance < amounts.juno_amount {
+                return Err(ContractError::InsufficientUserBalance { });
+            }
+        },
+        None => check_shared_balance(deps.querier, deps.storage, env, amounts.swap_amount)?,
+    }
+
+    Ok((pool_info, amounts, user))
+}
+
+fn buy_token(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    params: BuyParams,
+    pool: Addr,
+    intent: Option<SignedBuyIntent>,
+    callback: Option<Binary>,
+) -> Result<Response, ContractError> {
+    
+    check_bot_role(deps.storage, &info.sender)?;
+    let (pool_info, amounts, user) = check_buy_token(deps.as_ref(), &env, &params, &pool, intent.as_ref())?;
+    let recipient = params.recipient;
+
+    if let (Some(user), Some(signed)) = (&user, &intent) {
+        INTENT_NONCES.save(deps.storage, (user.clone(), signed.intent.nonce), &true)?;
+        debit_user(deps.storage, user, &Denom::Native(String::from("ujuno")), amounts.juno_amount)?;
+    }
+
+    let (mut messages, sweep) = sweep_atom(deps.querier, deps.storage, &env, &info.sender)?;
+
+    // with a callback the output comes here first and is passed on in the reply
+    let swap_recipient = match callback {
+        Some(_) => None,
+        None => Some(recipient.clone()),
+    };
+    let (token2_amount, token2_denom, mut messages_swap) = 
+        venue::get_venue(&pool_info.venue).swap(deps.querier
+            , PoolRef { address: &pool, info: &pool_info }
+            , Denom::Native(String::from("ujuno"))
+            , amounts.swap_amount
+            , amounts.amount_out_min
+            , swap_recipient)?;
+    messages.append(&mut messages_swap);    
+
+    record_buy(deps.storage, &info.sender, &recipient, &amounts)?;
+    // points only go to recipients spending their own deposit
+    let (loyalty_mint, loyalty_points) = match &user {
+        Some(user) if *user == recipient => mint_loyalty_points(deps.storage, &env, &recipient, &amounts)?,
+        _ => (vec![], Uint128::zero()),
+    };
+    let trade = save_trade(deps.storage, buy_trade(&env, &info.sender, &recipient, &pool, &amounts, token2_amount, token2_denom))?;
+    match callback {
+        Some(msg) => {
+            let buyer = user.unwrap_or_else(|| info.sender.clone());
+            save_pending_output(deps.querier, deps.storage, &trade, env.contract.address.clone(), sweep.as_ref(), Some(PurchaseCallback { buyer, msg }), None)?;
+        },
+        None => save_pending_output(deps.querier, deps.storage, &trade, recipient, sweep.as_ref(), None, None)?,
+    }
+
+    Ok(Response::new()
+        .add_submessages(reply_on_last(messages, REPLY_BUY_OUTPUT))
+        .add_messages(loyalty_mint)
+        .add_attribute("action", "buy_token")
+        .add_attribute("loyalty_points", loyalty_points)
+        .add_events(sweep.iter().map(events::sweep_event))
+        .add_event(events::buy_event(&trade)))
+}
+
+/// Returns the enabled registered pools that trade ujuno against `target_denom`
+fn get_pools_for_pair(
+    storage: &dyn Storage,
+    target_denom: &Denom,
+) -> StdResult<Vec<(Addr, PoolInfo)>> {
+    let juno_denom = Denom::Native(String::from("ujuno"));
+
+    POOLS
+        .range(storage, None, None, Order::Ascending)
+        .filter(|item| match item {
+            Ok((_, pool)) => pool.enabled
+                && ((pool.token1_denom == juno_denom && pool.token2_denom == *target_denom)
+                    || (pool.token2_denom == juno_denom && pool.token1_denom == *target_denom)),
+            Err(_) => true,
+        })
+        .collect()
+}
+
+/// Quotes `juno_amount` against every registered pool for the pair and returns the one paying out the most
+fn select_best_pool(
+    querier: QuerierWrapper,
+    storage: &dyn Storage,
+    target_denom: &Denom,
+    juno_amount: Uint128,
+) -> Result<(Addr, PoolInfo, Uint128), ContractError> {
+    let mut best: Option<(Addr, PoolInfo, Uint128)> = None;
+
+    for (pool_address, pool_info) in get_pools_for_pair(storage, target_denom)? {
+        let (swap_amount, _denom) = venue::get_venue(&pool_info.venue).quote(querier
+            , PoolRef { address: &pool_address, info: &pool_info }
+            , Denom::Native(String::from("ujuno"))
+            , juno_amount)?;
+
+        if best.as_ref().map_or(true, |(_, _, best_amount)| swap_amount > *best_amount) {
+            best = Some((pool_address, pool_info, swap_amount));
+        }
+    }
+
+    best.ok_or(ContractError::NoPoolForPair { })
+}
+
+fn buy_token_best_pool(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    params: BuyParams,
+    target_denom: Denom,
+) -> Result<Response, ContractError> {
+
+    check_buy_preconditions(deps.storage, &info.sender, &env, params.slippage_bips, params.deadline)?;
+
+    let amounts = compute_buy_amounts(deps.querier, deps.storage, &params)?;
+    let recipient = params.recipient;
+    check_shared_balance(deps.querier, deps.storage, &env, amounts.swap_amount)?;
+
+    let (pool, pool_info, quoted_amount) = select_best_pool(deps.querier, deps.storage, &target_denom, amounts.swap_amount)?;
+
+    let (mut messages, sweep) = sweep_atom(deps.querier, deps.storage, &env, &info.sender)?;
+
+    let (token2_amount, token2_denom, mut messages_swap) = 
+        venue::get_venue(&pool_info.venue).swap(deps.querier
+            , PoolRef { address: &pool, info: &pool_info }
+            , Denom::Native(String::from("ujuno"))
+            , amounts.swap_amount
+            , amounts.amount_out_min
+            , Some(recipient.clone()))?;
+    messages.append(&mut messages_swap);    
+
+    record_buy(deps.storage, &info.sender, &recipient, &amounts)?;
+    let trade = save_trade(deps.storage, buy_trade(&env, &info.sender, &recipient, &pool, &amounts, token2_amount, token2_denom))?;
+    save_pending_output(deps.querier, deps.storage, &trade, recipient, sweep.as_ref(), None, None)?;
+
+    Ok(Response::new()
+        .add_submessages(reply_on_last(messages, REPLY_BUY_OUTPUT))
+        .add_attribute("action", "buy_token_best_pool")
+        .add_attribute("pool", pool)
+        .add_attribute("quoted_amount", quoted_amount)
+        .add_events(sweep.iter().map(events::sweep_event))
+        .add_event(events::buy_event(&trade))
+    )
+}
+
+/// Splits `juno_amount` across up to `max_pools` pools for the pair. The input is handed out in
+/// SPLIT_STEPS chunks, each going to the pool whose constant product curve pays most for it.
+/// Every venue is modelled with the wasmswap 0.3% fee, the actual outputs are quoted afterwards.
+fn split_across_pools(
+    querier: QuerierWrapper,
+    storage: &dyn Storage,
+    target_denom: &Denom,
+    juno_amount: Uint128,
+    max_pools: u32,
+) -> Result<Vec<(Addr, PoolInfo, Uint128)>, ContractError> {
+    let juno_denom = Denom::Native(String::from("ujuno"));
+
+    let mut pools: Vec<(Addr, PoolInfo, Uint128, Uint128)> = vec![];
+    for (pool_address, pool_info) in get_pools_for_pair(storage, target_denom)? {
+        let (input_reserve, output_reserve) = venue::get_venue(&pool_info.venue).pool_reserves(querier, pool_address.clone(), juno_denom.clone())?;
+        pools.push((pool_address, pool_info, input_reserve, output_reserve));
+    }
+    if pools.is_empty() {
+        return Err(ContractError::NoPoolForPair { });
+    }
+
+    // keep the pools that would pay most for the whole amount on their own
+    pools.sort_by_key(|(_, _, input_reserve, output_reserve)| std::cmp::Reverse(util::get_input_price(juno_amount, *input_reserve, *output_reserve)));
+    pools.truncate(max_pools as usize);
+
+    let mut allocations = vec![Uint128::zero(); pools.len()];
+    let chunk = juno_amount / Uint128::from(SPLIT_STEPS);
+    let mut remaining = juno_amount;
+    while !remaining.is_zero() {
+        let step = if chunk.is_zero() || remaining < chunk + chunk { remaining } else { chunk };
+
+        let mut best_index = 0;
+        let mut best_gain = Uint128::zero();
+        for (index, (_, _, input_reserve, output_reserve)) in pools.iter().enumerate() {
+            let current = util::get_input_price(allocations[index], *input_reserve, *output_reserve);
+            let next = util::get_input_price(allocations[index] + step, *input_reserve, *output_reserve);
+            if next - current > best_gain {
+                best_index = index;
+                best_gain = next - current;
+            }
+        }
+
+        allocations[best_index] += step;
+        remaining -= step;
+    }
+
+    Ok(pools.into_iter()
+        .zip(allocations)
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|((pool_address, pool_info, _, _), amount)| (pool_address, pool_info, amount))
+        .collect())
+}
+
+fn buy_token_split(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    params: BuyParams,
+    target_denom: Denom,
+    max_pools: u32,
+) -> Result<Response, ContractError> {
+
+    check_buy_preconditions(deps.storage, &info.sender, &env, params.slippage_bips, params.deadline)?;
+
+    if max_pools == 0 {
+        return Err(ContractError::InvalidSplit { });
+    }
+
+    let amounts = compute_buy_amounts(deps.querier, deps.storage, &params)?;
+    let recipient = params.recipient;
+    check_shared_balance(deps.querier, deps.storage, &env, amounts.swap_amount)?;
+
+    let splits = split_across_pools(deps.querier, deps.storage, &target_denom, amounts.swap_amount, max_pools)?;
+
+    let (mut messages, sweep) = sweep_atom(deps.querier, deps.storage, &env, &info.sender)?;
+
+    let mut response = Response::new()
+        .add_attribute("action", "buy_token_split")
+        .add_events(sweep.iter().map(events::sweep_event));
+
+    // each swap is bounded by its own quote, the bot's min output applies to the total
+    let mut total_amount = Uint128::zero();
+    let mut legs: Vec<(Addr, Uint128, Uint128, Denom)> = vec![];
+    for (pool, pool_info, split_amount) in splits {
+        let (token2_amount, token2_denom, mut messages_swap) = 
+            venue::get_venue(&pool_info.venue).swap(deps.querier
+                , PoolRef { address: &pool, info: &pool_info }
+                , Denom::Native(String::from("ujuno"))
+                , split_amount
+                , Uint128::zero()
+                , Some(recipient.clone()))?;
+        messages.append(&mut messages_swap);
+        total_amount += token2_amount;
+
+        response = response
+            .add_attribute("pool", pool.clone())
+            .add_attribute("pool_input", split_amount)
+            .add_attribute("pool_output", token2_amount);
+        legs.push((pool, split_amount, token2_amount, token2_denom));
+    }
+
+    if total_amount < amounts.amount_out_min {
+        return Err(ContractError::InsufficientOutputAmount{});
+    }
+
+    record_buy(deps.storage, &info.sender, &recipient, &amounts)?;
+
+    // one trade per pool, the fee, gas and min output are shared out by each pool's input, the last pool takes the rounding
+    let mut fee_left = amounts.platform_fee;
+    let mut gas_left = amounts.gas_reimbursement;
+    let mut min_left = amounts.amount_out_min;
+    let leg_count = legs.len();
+    for (index, (pool, split_amount, token2_amount, token2_denom)) in legs.into_iter().enumerate() {
+        let (platform_fee, gas_reimbursement, amount_out_min) = if index + 1 == leg_count {
+            (fee_left, gas_left, min_left)
+        } else {
+            (amounts.platform_fee.multiply_ratio(split_amount, amounts.swap_amount),
+                amounts.gas_reimbursement.multiply_ratio(split_amount, amounts.swap_amount),
+                amounts.amount_out_min.multiply_ratio(split_amount, amounts.swap_amount))
+        };
+        fee_left -= platform_fee;
+        gas_left -= gas_reimbursement;
+        min_left -= amount_out_min;
+
+        let trade = save_trade(deps.storage, Trade {
+            input_amount: split_amount + platform_fee + gas_reimbursement,
+            platform_fee,
+            gas_reimbursement,
+            amount_out_min,
+            ..buy_trade(&env, &info.sender, &recipient, &pool, &amounts, token2_amount, token2_denom)
+        })?;
+        response = response.add_event(events::buy_event(&trade));
+    }
+
+    Ok(response
+        .add_messages(messages)
+        .add_attribute("quoted_amount", total_amount)
+    )
+}
+
+/// Works out the gross ujuno a buy of exactly `desired_output` needs: the pool input from the wasmswap
+/// reserves, grossed up for the gas reimbursement and the platform fee taken on the gross amount
+fn compute_exact_out_input(
+    querier: QuerierWrapper,
+    storage: &dyn Storage,
+    desired_output: Uint128,
+    recipient: &Addr,
+    pool: Addr,
+    platform_fee_bips: Option<Uint128>,
+    gas_estimate: Uint128,
+) -> Result<Uint128, ContractError> {
+    let pool_info = load_enabled_pool(storage, pool.clone())?;
+    if pool_info.venue != Venue::Wasmswap {
+        return Err(ContractError::UnsupportedVenue { });
+    }
+
+    let (input_reserve, output_reserve) = util::get_pool_reserves(querier, pool, Denom::Native(String::from("ujuno")))?;
+    let swap_amount = util::get_output_price(desired_output, input_reserve, output_reserve)?;
+
+    let (platform_fee_bips, _volume) = resolve_fee_bips(querier, storage, recipient, platform_fee_bips)?;
+    let gas_reimbursement = resolve_gas_reimbursement(storage, gas_estimate)?;
+
+    let net_bips = Uint128::from(MAX_BIPS).checked_sub(platform_fee_bips)
+        .map_err(|_| ContractError::PlatformFeeOutOfBounds { })?;
+    if net_bips.is_zero() {
+        return Err(ContractError::PlatformFeeOutOfBounds { });
+    }
+
+    let net_amount = swap_amount + gas_reimbursement;
+    let juno_amount = (net_amount * Uint128::from(MAX_BIPS) + net_bips - Uint128::from(1u128)) / net_bips;
+    Ok(juno_amount)
+}
+
+/// Buys exactly `desired_output` with the ujuno an enabled bot attached to the call, `params.juno_amount`,
+/// and refunds the rest
+fn buy_token_exact_out(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    params: BuyParams,
+    pool: Addr,
+    desired_output: Uint128,
+) -> Result<Response, ContractError> {
+
+    check_buy_preconditions(deps.storage, &info.sender, &env, params.slippage_bips, params.deadline)?;
+
+    // anything but ujuno would be stuck, neither spent nor refunded
+    if info.funds.iter().any(|coin| coin.denom != "ujuno") {
+        return Err(ContractError::UnsupportedDenom { });
+    }
+
+    let sent_amount = params.juno_amount;
+    let recipient = params.recipient.clone();
+
+    let juno_amount = compute_exact_out_input(deps.querier, deps.storage, desired_output, &recipient, pool.clone(), params.platform_fee_bips, params.gas_estimate)?;
+    if juno_amount > sent_amount {
+        return Err(ContractError::InsufficientToken { });
+    }
+
+    let amounts = compute_buy_amounts(deps.querier, deps.storage, &BuyParams { juno_amount, ..params })?;
+
+    let pool_info = load_enabled_pool(deps.storage, pool.clone())?;
+    let (mut messages, sweep) = sweep_atom(deps.querier, deps.storage, &env, &info.sender)?;
+
+    let (token2_amount, token2_denom, mut messages_swap) = 
+        util::get_swap_amount_and_denom_and_message(deps.querier
+            , pool.clone()
+            , &pool_info
+            , Denom::Native(String::from("ujuno"))
+            , amounts.swap_amount
+            , desired_output
+            , Some(recipient.clone()))?;
+    messages.append(&mut messages_swap);
+
+    // the refund goes after the swap, which has to stay last to reply
+    let refund = sent_amount - juno_amount;
+    let mut refund_messages = vec![];
+    if refund > Uint128::zero() {
+        refund_messages.push(util::transfer_token_message(Denom::Native(String::from("ujuno")), refund, info.sender.clone())?);
+    }
+
+    record_buy(deps.storage, &info.sender, &recipient, &amounts)?;
+    let trade = save_trade(deps.storage, Trade {
+        amount_out_min: desired_output,
+        ..buy_trade(&env, &info.sender, &recipient, &pool, &amounts, token2_amount, token2_denom)
+    })?;
+    save_pending_output(deps.querier, deps.storage, &trade, recipient, sweep.as_ref(), None, None)?;
+
+    Ok(Response::new()
+        .add_submessages(reply_on_last(messages, REPLY_BUY_OUTPUT))
+        .add_messages(refund_messages)
+        .add_attribute("action", "buy_token_exact_out")
+        .add_attribute("juno_amount", juno_amount)
+        .add_attribute("quoted_amount", token2_amount)
+        .add_attribute("refund", refund)
+        .add_events(sweep.iter().map(events::sweep_event))
+        .add_event(events::buy_event(&trade))
+    )
+}
+
+/// Swaps the attached ujuno into the contract. The output is measured and sent over IBC in the
+/// swap's reply, the transfer's own reply then tracks it until ibc-hooks reports its ack or timeout.
+fn buy_token_and_ibc_send(
+    deps: DepsMut,
+    env: Env,
+    params: BuyParams,
+    pool: Addr,
+    channel: String,
+    remote_recipient: String,
+    timeout: u64,
+) -> Result<Response, ContractError> {
+
+    check_buy_terms(&env, params.slippage_bips, params.deadline)?;
+    if timeout == 0 || timeout > MAX_IBC_TIMEOUT {
+        return Err(ContractError::InvalidTimeout { });
+    }
+
+    // the sender pays and receives
+    let user = params.recipient.clone();
+    let pool_info = load_enabled_pool(deps.storage, pool.clone())?;
+    let amounts = compute_buy_amounts(deps.querier, deps.storage, &params)?;
+
+    let (token2_amount, token2_denom, messages_swap) = 
+        venue::get_venue(&pool_info.venue).swap(deps.querier
+            , PoolRef { address: &pool, info: &pool_info }
+            , Denom::Native(String::from("ujuno"))
+            , amounts.swap_amount
+            , amounts.amount_out_min
+            , None)?;
+    let denom = match &token2_denom {
+        Denom::Native(native_str) => native_str.clone(),
+        Denom::Cw20(_) => return Err(ContractError::UnsupportedDenom { }),
+    };
+
+    record_buy(deps.storage, &user, &user, &amounts)?;
+    let trade = save_trade(deps.storage, buy_trade(&env, &user, &user, &pool, &amounts, token2_amount, token2_denom.clone()))?;
+
+    PENDING_IBC_SEND.save(deps.storage, &IbcSend {
+        trade_id: trade.id,
+        user,
+        channel: channel.clone(),
+        remote_recipient: remote_recipient.clone(),
+        timeout: env.block.time.plus_seconds(timeout).seconds(),
+        denom,
+        balance_before: util::get_token_amount(deps.querier, token2_denom, env.contract.address.clone())?,
+        amount: Uint128::zero(),
+    })?;
+
+    Ok(Response::new()
+        .add_submessages(reply_on_last(messages_swap, REPLY_IBC_SWAP))
+        .add_attribute("action", "buy_token_and_ibc_send")
+        .add_attribute("channel", channel)
+        .add_attribute("remote_recipient", remote_recipient)
+        .add_event(events::buy_event(&trade))
+    )
+}
+
+/// Wraps the messages so the last one, the swap itself, replies on success
+fn reply_on_last(messages: Vec<CosmosMsg>, id: u64) -> Vec<SubMsg> {
+    let mut messages: Vec<SubMsg> = messages.into_iter().map(SubMsg::new).collect();
+    if let Some(swap) = messages.last_mut() {
+        *swap = SubMsg::reply_on_success(swap.msg.clone(), id);
+    }
+    messages
+}
+
+/// Remembers the holder's balance of the trade's output so the reply can measure what arrived
+fn save_pending_output(
+    querier: QuerierWrapper,
+    storage: &mut dyn Storage,
+    trade: &Trade,
+    holder: Addr,
+    sweep: Option<&Trade>,
+    callback: Option<PurchaseCallback>,
+    vesting: Option<VestingTerms>,
+) -> StdResult<()> {
+    let mut balance_before = util::get_token_amount(querier, trade.output_denom.clone(), holder.clone())?;
+    // the ATOM sweep runs before the swap and takes its input out of the holder's balance
+    if let Some(sweep) = sweep {
+        if sweep.recipient == holder && sweep.input_denom == trade.output_denom {
+            balance_before = balance_before.saturating_sub(sweep.input_amount);
+        }
+    }
+    PENDING_OUTPUT.save(storage, &PendingOutput { trade_id: trade.id, holder, balance_before, callback, vesting })
+}
+
+/// Hands a purchase the contract holds to the recipient contract along with the buyer's callback
+fn purchase_callback_message(
+    recipient: &Addr,
+    denom: Denom,
+    amount: Uint128,
+    callback: PurchaseCallback,
+) -> StdResult<CosmosMsg> {
+    let hook = to_binary(&PurchaseHookMsg::ReceivePurchase {
+        buyer: callback.buyer.into(),
+        amount,
+        denom: denom.clone(),
+        msg: callback.msg,
+    })?;
+
+    Ok(match denom {
+        Denom::Native(native_str) => CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: recipient.into(),
+            msg: hook,
+            funds: vec![Coin { denom: native_str, amount }],
+        }),
+        Denom::Cw20(cw20_address) => CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: cw20_address.into(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: recipient.into(),
+                amount,
+                msg: hook,
+            })?,
+            funds: vec![],
+        }),
+    })
+}
+
+fn buy_token_vested(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    params: BuyParams,
+    pool: Addr,
+    terms: VestingTerms,
+) -> Result<Response, ContractError> {
+
+    check_buy_preconditions(deps.storage, &info.sender, &env, params.slippage_bips, params.deadline)?;
+
+    if terms.duration == 0 || terms.cliff > terms.duration {
+        return Err(ContractError::InvalidVesting { });
+    }
+
+    let pool_info = load_enabled_pool(deps.storage, pool.clone())?;
+
+    let amounts = compute_buy_amounts(deps.querier, deps.storage, &params)?;
+    let recipient = params.recipient;
+    check_shared_balance(deps.querier, deps.storage, &env, amounts.swap_amount)?;
+
+    let (mut messages, sweep) = sweep_atom(deps.querier, deps.storage, &env, &info.sender)?;
+
+    let (token2_amount, token2_denom, mut messages_swap) = 
+        venue::get_venue(&pool_info.venue).swap(deps.querier
+            , PoolRef { address: &pool, info: &pool_info }
+            , Denom::Native(String::from("ujuno"))
+            , amounts.swap_amount
+            , amounts.amount_out_min
+            , None)?;
+    messages.append(&mut messages_swap);
+
+    record_buy(deps.storage, &info.sender, &recipient, &amounts)?;
+    let trade = save_trade(deps.storage, buy_trade(&env, &info.sender, &recipient, &pool, &amounts, token2_amount, token2_denom))?;
+    save_pending_output(deps.querier, deps.storage, &trade, env.contract.address.clone(), sweep.as_ref(), None, Some(terms.clone()))?;
+
+    Ok(Response::new()
+        .add_submessages(reply_on_last(messages, REPLY_BUY_OUTPUT))
+        .add_attribute("action", "buy_token_vested")
+        .add_attribute("cliff", terms.cliff.to_string())
+        .add_attribute("duration", terms.duration.to_string())
+        .add_events(sweep.iter().map(events::sweep_event))
+        .add_event(events::buy_event(&trade))
+    )
+}
+
+fn save_vesting(
+    storage: &mut dyn Storage,
+    env: &Env,
+    trade: &Trade,
+    amount: Uint128,
+    terms: VestingTerms,
+) -> StdResult<u64> {
+    let id = VESTING_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    VESTING_COUNT.save(storage, &id)?;
+
+    let key = denom_key(&trade.output_denom);
+    let total = VESTING_TOTALS.may_load(storage, &key)?.unwrap_or_default();
+    VESTING_TOTALS.save(storage, &key, &(total + amount))?;
+
+    let start = env.block.time.seconds();
+    VESTINGS.save(storage, (trade.recipient.clone(), id), &Vesting {
+        id,
+        trade_id: trade.id,
+        denom: trade.output_denom.clone(),
+        total: amount,
+        claimed: Uint128::zero(),
+        start,
+        cliff: start + terms.cliff,
+        end: start + terms.duration,
+    })?;
+    Ok(id)
+}
+
+fn get_vested_amount(vesting: &Vesting, now: u64) -> Uint128 {
+    if now < vesting.cliff {
+        Uint128::zero()
+    } else if now >= vesting.end {
+        vesting.total
+    } else {
+        vesting.total.multiply_ratio(now - vesting.start, vesting.end - vesting.start)
+    }
+}
+
+/// Pays out what has vested across all of the sender's vestings, one transfer per denom
+fn try_claim_vested(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+) -> Result<Response, ContractError> {
+    let now = env.block.time.seconds();
+    let vestings = VESTINGS
+        .prefix(info.sender.clone())
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, vesting)| vesting))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut claims: Vec<(Denom, Uint128)> = vec![];
+    for mut vesting in vestings {
+        let claimable = get_vested_amount(&vesting, now) - vesting.claimed;
+        if claimable.is_zero() {
+            continue;
+        }
+
+        vesting.claimed += claimable;
+        let key = denom_key(&vesting.denom);
+        let total = VESTING_TOTALS.load(deps.storage, &key)?;
+        VESTING_TOTALS.save(deps.storage, &key, &(total - claimable))?;
+        if vesting.claimed == vesting.total {
+            VESTINGS.remove(deps.storage, (info.sender.clone(), vesting.id));
+        } else {
+            VESTINGS.save(deps.storage, (info.sender.clone(), vesting.id), &vesting)?;
+        }
+
+        match claims.iter_mut().find(|(denom, _)| *denom == vesting.denom) {
+            Some((_, amount)) => *amount += claimable,
+            None => claims.push((vesting.denom, claimable)),
+        }
+    }
+    if claims.is_empty() {
+        return Err(ContractError::NothingToClaim { });
+    }
+
+    let mut response = Response::new()
+        .add_attribute("action", "claim_vested")
+        .add_attribute("recipient", info.sender.clone());
+    for (denom, amount) in claims {
+        response = response
+            .add_message(util::transfer_token_message(denom.clone(), amount, info.sender.clone())?)
+            .add_attribute("denom", events::denom_attribute(&denom))
+            .add_attribute("amount", amount);
+    }
+    Ok(response)
+}
+
+#[entry_point]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        REPLY_BUY_OUTPUT => reply_buy_output(deps, env),
+        REPLY_BUYBACK => reply_buyback(deps, env),
+        REPLY_COMPOUND => reply_compound(deps, env),
+        REPLY_REMOVE_LIQUIDITY => reply_remove_liquidity(deps, env),
+        REPLY_IBC_SWAP => reply_ibc_swap(deps, env),
+        REPLY_IBC_TRANSFER => reply_ibc_transfer(deps, msg),
+        _ => Err(ContractError::UnknownReplyId { }),
+    }
+}
+
+/// Sets the response data to the trade and the output it actually delivered, passing it on
+/// to the recipient if the buy came with a callback or locking it if it vests
+fn reply_buy_output(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending = PENDING_OUTPUT.load(deps.storage)?;
+    PENDING_OUTPUT.remove(deps.storage);
+
+    let trade = trades().load(deps.storage, pending.trade_id)?;
+    let balance_after = util::get_token_amount(deps.querier, trade.output_denom.clone(), pending.holder)?;
+    let actual_output = balance_after.checked_sub(pending.balance_before).map_err(StdError::from)?;
+
+    let mut response = Response::new();
+    if let Some(callback) = pending.callback {
+        response = response
+            .add_message(purchase_callback_message(&trade.recipient, trade.output_denom.clone(), actual_output, callback)?)
+            .add_attribute("callback", trade.recipient.clone());
+    }
+    if let Some(terms) = pending.vesting {
+        let vesting_id = save_vesting(deps.storage, &env, &trade, actual_output, terms)?;
+        response = response.add_attribute("vesting_id", vesting_id.to_string());
+    }
+
+    Ok(response
+        .set_data(to_binary(&BuyTokenResponse {
+            trade_id: trade.id,
+            input_denom: trade.input_denom,
+            input_amount: trade.input_amount,
+            platform_fee: trade.platform_fee,
+            output_denom: trade.output_denom,
+            quoted_output: trade.quoted_output,
+            actual_output,
+        })?)
+        .add_attribute("trade_id", trade.id.to_string())
+        .add_attribute("actual_output", actual_output)
+    )
+}
+
+/// Burns the buyback tokens the swap delivered
+fn reply_buyback(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let balance_before = PENDING_BURN.load(deps.storage)?;
+    PENDING_BURN.remove(deps.storage);
+
+    let buyback = BUYBACK_CONFIG.load(deps.storage)?;
+    let balance_after = util::get_token_amount(deps.querier, Denom::Cw20(buyback.token.clone()), env.contract.address)?;
+    let burned = balance_after.checked_sub(balance_before).map_err(StdError::from)?;
+
+    let total_burned = TOTAL_BURNED.may_load(deps.storage)?.unwrap_or_default() + burned;
+    TOTAL_BURNED.save(deps.storage, &total_burned)?;
+
+    // a CW20 burn of zero fails, and with it the whole buyback
+    let mut response = Response::new();
+    if !burned.is_zero() {
+        response = response.add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: buyback.token.clone().into(),
+            msg: to_binary(&Cw20ExecuteMsg::Burn { amount: burned })?,
+            funds: vec![],
+        }));
+    }
+
+    Ok(response
+        .add_attribute("burned", burned)
+        .add_attribute("total_burned", total_burned)
+    )
+}
+
+/// Adds the tokens the compound swap delivered and the ujuno kept back to the pool, sized against
+/// the reserves the swap left behind. ujuno the deposit does not use goes back to the fee ledger,
+/// the pair token it does not use stays in the contract and is reported as token_unused.
+fn reply_compound(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending = PENDING_COMPOUND.load(deps.storage)?;
+    PENDING_COMPOUND.remove(deps.storage);
+
+    let liquidity = LIQUIDITY_CONFIG.load(deps.storage)?;
+    let balance_after = util::get_token_amount(deps.querier, pending.token_denom.clone(), env.contract.address)?;
+    let token_amount = balance_after.checked_sub(pending.balance_before).map_err(StdError::from)?;
+
+    let juno_denom = Denom::Native(String::from("ujuno"));
+    let pool_info_response = util::get_pool_info(deps.querier, liquidity.pool.clone())?;
+    let (token1_available, token2_available) = if pool_info_response.token1_denom == juno_denom {
+        (pending.juno_left, token_amount)
+    } else {
+        (token_amount, pending.juno_left)
+    };
+    let token1_reserve = pool_info_response.token1_reserve;
+    let token2_reserve = pool_info_response.token2_reserve;
+
+    let mut token1_amount = token1_available;
+    let mut token2_amount = util::get_token2_amount_required(token1_amount, token1_reserve, token2_reserve);
+    if token2_amount > token2_available {
+        token1_amount = token2_available.saturating_sub(Uint128::from(1u128)).multiply_ratio(token1_reserve, token2_reserve);
+        token2_amount = util::get_token2_amount_required(token1_amount, token1_reserve, token2_reserve);
+    }
+    if token1_amount.is_zero() {
+        return Err(ContractError::InsufficientToken { });
+    }
+
+    let liquidity_amount = token1_amount.multiply_ratio(pool_info_response.lp_token_supply, token1_reserve);
+    let min_liquidity = liquidity_amount.multiply_ratio(Uint128::from(MAX_BIPS) - liquidity.max_slippage_bips, MAX_BIPS);
+
+    let juno_used = if pool_info_response.token1_denom == juno_denom { token1_amount } else { token2_amount };
+    let juno_unused = pending.juno_left - juno_used;
+    let token_used = if pool_info_response.token1_denom == juno_denom { token2_amount } else { token1_amount };
+    let token_unused = token_amount - token_used;
+    let state = config(deps.storage).update(|mut state| -> StdResult<_> {
+        state.pending_platform_fee += juno_unused;
+        Ok(state)
+    })?;
+    add_to_denom_total(deps.storage, &FEES_WITHDRAWN_BY_DENOM, &juno_denom, pending.amount - juno_unused)?;
+
+    Ok(Response::new()
+        .add_messages(util::add_liquidity_messages(liquidity.pool
+            , pool_info_response.token1_denom
+            , pool_info_response.token2_denom
+            , token1_amount
+            , token2_amount
+            , min_liquidity)?)
+        .add_attribute("token1_amount", token1_amount)
+        .add_attribute("token2_amount", token2_amount)
+        .add_attribute("min_liquidity", min_liquidity)
+        .add_attribute("token_unused", token_unused)
+        .add_attribute("pending_platform_fee", state.pending_platform_fee)
+    )
+}
+
+/// Sends both tokens the liquidity removal paid the contract on to `to`
+fn reply_remove_liquidity(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending = PENDING_REMOVE_LIQUIDITY.load(deps.storage)?;
+    PENDING_REMOVE_LIQUIDITY.remove(deps.storage);
+
+    let token1_after = util::get_token_amount(deps.querier, pending.token1_denom.clone(), env.contract.address.clone())?;
+    let token1_amount = token1_after.checked_sub(pending.token1_balance_before).map_err(StdError::from)?;
+    let token2_after = util::get_token_amount(deps.querier, pending.token2_denom.clone(), env.contract.address)?;
+    let token2_amount = token2_after.checked_sub(pending.token2_balance_before).map_err(StdError::from)?;
+
+    let mut response = Response::new();
+    for (denom, amount) in [(pending.token1_denom, token1_amount), (pending.token2_denom, token2_amount)] {
+        if !amount.is_zero() {
+            response = response.add_message(util::transfer_token_message(denom, amount, pending.to.clone())?);
+        }
+    }
+
+    Ok(response
+        .add_attribute("token1_amount", token1_amount)
+        .add_attribute("token2_amount", token2_amount)
+    )
+}
+
+fn reply_ibc_swap(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let mut send = PENDING_IBC_SEND.load(deps.storage)?;
+
+    let balance_after = util::get_token_amount(deps.querier, Denom::Native(send.denom.clone()), env.contract.address.clone())?;
+    send.amount = balance_after.checked_sub(send.balance_before).map_err(StdError::from)?;
+    if send.amount.is_zero() {
+        return Err(ContractError::InsufficientOutputAmount { });
+    }
+    PENDING_IBC_SEND.save(deps.storage, &send)?;
+
+    let transfer = util::ibc_transfer_message(&env.contract.address
+        , &send.channel
+        , &send.remote_recipient
+        , &Coin { denom: send.denom.clone(), amount: send.amount }
+        , Timestamp::from_seconds(send.timeout).nanos())?;
+
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_always(transfer, REPLY_IBC_TRANSFER))
+        .add_attribute("action", "ibc_send")
+        .add_attribute("trade_id", send.trade_id.to_string())
+        .add_attribute("denom", send.denom)
+        .add_attribute("amount", send.amount)
+    )
+}
+
+/// A transfer rejected outright is credited to the user straight away, one in flight is
+/// tracked under the sequence of its send_packet event
+fn reply_ibc_transfer(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let send = PENDING_IBC_SEND.load(deps.storage)?;
+    PENDING_IBC_SEND.remove(deps.storage);
+
+    let response = match msg.result.into_result() {
+        Ok(response) => response,
+        Err(err) => {
+            credit_user(deps.storage, &send.user, &Denom::Native(send.denom.clone()), send.amount)?;
+            return Ok(Response::new()
+                .add_attribute("action", "ibc_send_failed")
+                .add_attribute("trade_id", send.trade_id.to_string())
+                .add_attribute("user", send.user)
+                .add_attribute("error", err));
+        },
+    };
+
+    let sequence = response.events.iter()
+        .filter(|event| event.ty == "send_packet")
+        .flat_map(|event| event.attributes.iter())
+        .find(|attr| attr.key == "packet_sequence")
+        .and_then(|attr| attr.value.parse::<u64>().ok())
+        .ok_or_else(|| StdError::generic_err("send_packet sequence not found"))?;
+
+    IBC_TRANSFERS.save(deps.storage, (send.channel.clone(), sequence), &send)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "ibc_send_dispatched")
+        .add_attribute("trade_id", send.trade_id.to_string())
+        .add_attribute("channel", send.channel)
+        .add_attribute("sequence", sequence.to_string())
+    )
+}
+
+#[entry_point]
+pub fn sudo(deps: DepsMut, _env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    match msg {
+        SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcAck { channel, sequence, ack: _, success }) => {
+            try_complete_ibc_transfer(deps, channel, sequence, success)
+        },
+        SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout { channel, sequence }) => {
+            try_complete_ibc_transfer(deps, channel, sequence, false)
+        },
+    }
+}
+
+/// ICS20 refunds a failed or timed out transfer to the contract, which passes it on to the user's balance
+fn try_complete_ibc_transfer(
+    deps: DepsMut,
+    channel: String,
+    sequence: u64,
+    delivered: bool,
+) -> Result<Response, ContractError> {
+    let send = IBC_TRANSFERS.load(deps.storage, (channel.clone(), sequence))?;
+    IBC_TRANSFERS.remove(deps.storage, (channel, sequence));
+
+    if !delivered {
+        credit_user(deps.storage, &send.user, &Denom::Native(send.denom.clone()), send.amount)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "complete_ibc_transfer")
+        .add_attribute("trade_id", send.trade_id.to_string())
+        .add_attribute("user", send.user)
+        .add_attribute("delivered", delivered.to_string())
+    )
+}
+
+#[entry_point]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::GetInfos {token} => to_binary(&query_infos(deps, env, token)?),
+        QueryMsg::Config {} => to_binary(&query_config(deps, env)?),
+        QueryMsg::Balances { denoms } => to_binary(&query_balances(deps, env, denoms)?),
+        QueryMsg::Status {} => to_binary(&query_status(deps, env)?),
+        QueryMsg::FeeSchedule {} => to_binary(&query_fee_schedule(deps)?),
+        QueryMsg::RecipientFee { recipient } => to_binary(&query_recipient_fee(deps, recipient)?),
+        QueryMsg::GasReimbursement { bot } => to_binary(&query_gas_reimbursement(deps, bot)?),
+        QueryMsg::ListPools { start_after, limit } => to_binary(&query_list_pools(deps, start_after, limit)?),
+        QueryMsg::SimulateBuy { juno_amount, token_amount_per_native, slippage_bips, recipient, pool, platform_fee_bips, gas_estimate, deadline, bot, intent } => {
+            let recipient = deps.api.addr_validate(&recipient)?;
+            let params = BuyParams { juno_amount, token_amount_per_native, slippage_bips, recipient, platform_fee_bips, gas_estimate, deadline };
+            to_binary(&query_simulate_buy(deps, env, params, pool, bot, intent.map(|intent| *intent)))
+        },
+        QueryMsg::SimulateBuyExactOut { desired_output, pool, recipient, platform_fee_bips, gas_estimate } =>
+            to_binary(&query_simulate_buy_exact_out(deps, desired_output, pool, recipient, platform_fee_bips, gas_estimate)),
+        QueryMsg::TradesByRecipient { recipient, start_after, limit } => to_binary(&query_trades_by(deps, TradeIndex::Recipient, recipient, start_after, limit)?),
+        QueryMsg::TradesByBot { bot, start_after, limit } => to_binary(&query_trades_by(deps, TradeIndex::Bot, bot, start_after, limit)?),
+        QueryMsg::TradesByPool { pool, start_after, limit } => to_binary(&query_trades_by(deps, TradeIndex::Pool, pool, start_after, limit)?),
+        QueryMsg::Stats {} => to_binary(&query_stats(deps)?),
+        QueryMsg::UserBalances { user } => to_binary(&query_user_balances(deps, user)?),
+        QueryMsg::IntentNonce { user, nonce } => to_binary(&query_intent_nonce(deps, user, nonce)?),
+        QueryMsg::Vestings { recipient, start_after, limit } => to_binary(&query_vestings(deps, env, recipient, start_after, limit)?),
+        QueryMsg::Loyalty {} => to_binary(&query_loyalty(deps, env)?),
+        QueryMsg::HolderDiscount {} => to_binary(&HolderDiscountResponse {
+            config: HOLDER_DISCOUNT.may_load(deps.storage)?,
+        }),
+        QueryMsg::Buyback {} => to_binary(&BuybackResponse {
+            config: BUYBACK_CONFIG.may_load(deps.storage)?,
+            total_burned: TOTAL_BURNED.may_load(deps.storage)?.unwrap_or_default(),
+        }),
+        QueryMsg::LiquidityPosition {} => to_binary(&query_liquidity_position(deps, env)?),
+        QueryMsg::Staking {} => to_binary(&query_staking(deps, env)?),
+        QueryMsg::IbcTransfer { channel, sequence } => to_binary(&IbcTransferResponse {
+            transfer: IBC_TRANSFERS.may_load(deps.storage, (channel, sequence))?,
+        }),
+    }
+}
+
+fn query_fee_schedule(deps: Deps) -> StdResult<FeeScheduleResponse> {
+    let fee_config = FEE_CONFIG.load(deps.storage)?;
+
+    Ok(FeeScheduleResponse {
+        default_bips: fee_config.default_bips,
+        max_bips: fee_config.max_bips,
+        tiers: fee_config.tiers,
+    })
+}
+
+fn query_recipient_fee(deps: Deps, recipient: String) -> StdResult<RecipientFeeResponse> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let fee_config = FEE_CONFIG.load(deps.storage)?;
+    let (fee_bips, volume, overridden, discount_bips) = get_discounted_fee_bips(deps.querier, deps.storage, &fee_config, &recipient)?;
+
+    Ok(RecipientFeeResponse { fee_bips, volume, overridden, discount_bips })
+}
+
+fn query_infos(deps: Deps, env: Env, token: String) -> StdResult<AdminResponse> {
+    let state = config_read(deps.storage).load()?;
+    let admin = state.owner;
+    let pending_platform_fee = state.pending_platform_fee;
+    let blocktime = env.block.time.seconds();
+    let contract_address = env.contract.address.clone();
+    let token_balance = util::get_token_amount(deps.querier, Denom::Native(token), env.contract.address.clone())?;
+    let token_balances = util::get_tokens_amounts(deps.querier, env.contract.address)?;
+    
+    Ok(AdminResponse { admin, pending_platform_fee, blocktime, token_balance, token_balances, contract_address })
+}
+
+fn query_config(deps: Deps, env: Env) -> StdResult<ConfigResponse> {
+    let state = config_read(deps.storage).load()?;
+    let fee_config = FEE_CONFIG.load(deps.storage)?;
+    let gas_config = GAS_CONFIG.load(deps.storage)?;
+
+    Ok(ConfigResponse {
+        admin: state.owner,
+        contract_address: env.contract.address,
+        default_fee_bips: fee_config.default_bips,
+        max_fee_bips: fee_config.max_bips,
+        gas_price: gas_config.gas_price,
+        max_gas: gas_config.max_gas,
+    })
+}
+
+fn query_balances(deps: Deps, env: Env, denoms: Vec<Denom>) -> StdResult<BalancesResponse> {
+    let balances = denoms
+        .into_iter()
+        .map(|denom| {
+            let amount = util::get_token_amount(deps.querier, denom.clone(), env.contract.address.clone())?;
+            Ok(DenomBalance { denom, amount })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(BalancesResponse { balances })
+}
+
+fn query_status(deps: Deps, env: Env) -> StdResult<StatusResponse> {
+    let state = config_read(deps.storage).load()?;
+    let pending_gas_reimbursement = PENDING_GAS_REIMBURSEMENT.may_load(deps.storage)?.unwrap_or_default();
+    let juno_balance = util::get_token_amount(deps.querier, Denom::Native(String::from("ujuno")), env.contract.address)?;
+    let user_juno_deposits = USER_BALANCE_TOTALS.may_load(deps.storage, &denom_key(&Denom::Native(String::from("ujuno"))))?.unwrap_or_default();
+    let shared_balance = juno_balance.saturating_sub(user_juno_deposits);
+
+    Ok(StatusResponse {
+        block_height: env.block.height,
+        blocktime: env.block.time.seconds(),
+        pending_platform_fee: state.pending_platform_fee,
+        pending_gas_reimbursement,
+        juno_balance,
+        user_juno_deposits,
+        withdrawable_fee: std::cmp::min(state.pending_platform_fee, shared_balance.saturating_sub(pending_gas_reimbursement)),
+        trade_count: TRADE_COUNT.may_load(deps.storage)?.unwrap_or_default(),
+    })
+}
+
+
+
+fn query_gas_reimbursement(deps: Deps, bot: String) -> StdResult<GasReimbursementResponse> {
+    let bot = deps.api.addr_validate(&bot)?;
+    let gas_config = GAS_CONFIG.load(deps.storage)?;
+    let ledger = GAS_LEDGERS.may_load(deps.storage, bot.clone())?.unwrap_or_default();
+
+    Ok(GasReimbursementResponse {
+        bot,
+        accrued: ledger.accrued,
+        claimed: ledger.claimed,
+        claimable: ledger.accrued - ledger.claimed,
+        gas_price: gas_config.gas_price,
+        max_gas: gas_config.max_gas,
+    })
+}
+
+fn query_list_pools(deps: Deps, start_after: Option<String>, limit: Option<u32>) -> StdResult<PoolsResponse> {
+    let limit = limit.unwrap_or(util::DEFAULT_LIMIT).min(util::MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let pools = POOLS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (pool_address, pool) = item?;
+            Ok(PoolResponse {
+                pool_address,
+                token1_denom: pool.token1_denom,
+                token2_denom: pool.token2_denom,
+                enabled: pool.enabled,
+                venue: pool.venue,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(PoolsResponse { pools })
+}
+
+/// Runs the checks and math of `buy_token` without executing it
+fn simulate_buy(
+    deps: Deps,
+    env: Env,
+    params: BuyParams,
+    pool: String,
+    bot: Option<String>,
+    intent: Option<SignedBuyIntent>,
+) -> Result<SimulateBuyResponse, ContractError> {
+    let pool = validate_address(deps.api, &pool, "pool_address")?;
+
+    if let Some(bot) = bot {
+        let bot = validate_address(deps.api, &bot, "bot")?;
+        check_bot_role(deps.storage, &bot)?;
+    }
+    let (pool_info, amounts, _user) = check_buy_token(deps, &env, &params, &pool, intent.as_ref())?;
+
+    let (expected_output, output_denom) = venue::get_venue(&pool_info.venue).quote(deps.querier
+        , PoolRef { address: &pool, info: &pool_info }
+        , Denom::Native(String::from("ujuno"))
+        , amounts.swap_amount)?;
+    if expected_output < amounts.amount_out_min {
+        return Err(ContractError::InsufficientOutputAmount{});
+    }
+
+    Ok(SimulateBuyResponse {
+        expected_output,
+        amount_out_min: amounts.amount_out_min,
+        output_denom: Some(output_denom),
+        platform_fee: amounts.platform_fee,
+        gas_reimbursement: amounts.gas_reimbursement,
+        swap_amount: amounts.swap_amount,
+        error: None,
+    })
+}
+
+fn query_simulate_buy(
+    deps: Deps,
+    env: Env,
+    params: BuyParams,
+    pool: String,
+    bot: Option<String>,
+    intent: Option<SignedBuyIntent>,
+) -> SimulateBuyResponse {
+    simulate_buy(deps, env, params, pool, bot, intent)
+        .unwrap_or_else(|err| SimulateBuyResponse {
+            expected_output: Uint128::zero(),
+            amount_out_min: Uint128::zero(),
+            output_denom: None,
+            platform_fee: Uint128::zero(),
+            gas_reimbursement: Uint128::zero(),
+            swap_amount: Uint128::zero(),
+            error: Some(err.to_string()),
+        })
+}
+
+fn simulate_buy_exact_out(
+    deps: Deps,
+    desired_output: Uint128,
+    pool: String,
+    recipient: String,
+    platform_fee_bips: Option<Uint128>,
+    gas_estimate: Uint128,
+) -> Result<SimulateBuyExactOutResponse, ContractError> {
+    let recipient = validate_address(deps.api, &recipient, "recipient")?;
+    let pool = validate_address(deps.api, &pool, "pool_address")?;
+
+    let juno_amount = compute_exact_out_input(deps.querier, deps.storage, desired_output, &recipient, pool, platform_fee_bips, gas_estimate)?;
+    // the query has no deadline, BuyTokenExactOut checks its own
+    let params = BuyParams { juno_amount, token_amount_per_native: Uint128::zero(), slippage_bips: Uint128::zero(), recipient, platform_fee_bips, gas_estimate, deadline: Uint64::new(u64::MAX) };
+    let amounts = compute_buy_amounts(deps.querier, deps.storage, &params)?;
+
+    Ok(SimulateBuyExactOutResponse {
+        juno_amount,
+        platform_fee: amounts.platform_fee,
+        gas_reimbursement: amounts.gas_reimbursement,
+        swap_amount: amounts.swap_amount,
+        error: None,
+    })
+}
+
+fn query_simulate_buy_exact_out(
+    deps: Deps,
+    desired_output: Uint128,
+    pool: String,
+    recipient: String,
+    platform_fee_bips: Option<Uint128>,
+    gas_estimate: Uint128,
+) -> SimulateBuyExactOutResponse {
+    simulate_buy_exact_out(deps, desired_output, pool, recipient, platform_fee_bips, gas_estimate)
+        .unwrap_or_else(|err| SimulateBuyExactOutResponse {
+            juno_amount: Uint128::zero(),
+            platform_fee: Uint128::zero(),
+            gas_reimbursement: Uint128::zero(),
+            swap_amount: Uint128::zero(),
+            error: Some(err.to_string()),
+        })
+}
+
+enum TradeIndex {
+    Bot,
+    Recipient,
+    Pool,
+}
+
+fn query_trades_by(
+    deps: Deps,
+    index: TradeIndex,
+    addr: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<TradesResponse> {
+    let addr = deps.api.addr_validate(&addr)?;
+    let limit = limit.unwrap_or(util::DEFAULT_LIMIT).min(util::MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive_int);
+
+    let trades = trades();
+    let prefix = match index {
+        TradeIndex::Bot => trades.idx.bot.prefix(addr),
+        TradeIndex::Recipient => trades.idx.recipient.prefix(addr),
+        TradeIndex::Pool => trades.idx.pool.prefix(addr),
+    };
+
+    let trades = prefix
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, trade)| trade))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(TradesResponse { trades })
+}
+
+fn query_vestings(
+    deps: Deps,
+    env: Env,
+    recipient: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<VestingsResponse> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let limit = limit.unwrap_or(util::DEFAULT_LIMIT).min(util::MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive_int);
+    let now = env.block.time.seconds();
+
+    let vestings = VESTINGS
+        .prefix(recipient.clone())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (_, vesting) = item?;
+            let vested = get_vested_amount(&vesting, now);
+            Ok(VestingResponse { claimable: vested - vesting.claimed, vested, vesting })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(VestingsResponse { recipient, vestings })
+}
+
+fn query_liquidity_position(deps: Deps, env: Env) -> StdResult<LiquidityPositionResponse> {
+    let liquidity = LIQUIDITY_CONFIG.load(deps.storage)?;
+    let pool_info_response = util::get_pool_info(deps.querier, liquidity.pool.clone())
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    let lp_balance = util::get_token_amount(deps.querier, Denom::Cw20(liquidity.lp_token.clone()), env.contract.address)?;
+
+    let share = |reserve: Uint128| if pool_info_response.lp_token_supply.is_zero() {
+        Uint128::zero()
+    } else {
+        lp_balance.multiply_ratio(reserve, pool_info_response.lp_token_supply)
+    };
+
+    Ok(LiquidityPositionResponse {
+        token1_amount: share(pool_info_response.token1_reserve),
+        token2_amount: share(pool_info_response.token2_reserve),
+        token1_denom: pool_info_response.token1_denom,
+        token2_denom: pool_info_response.token2_denom,
+        lp_token_supply: pool_info_response.lp_token_supply,
+        lp_balance,
+        config: liquidity,
+    })
+}
+
+fn query_loyalty(deps: Deps, env: Env) -> StdResult<LoyaltyResponse> {
+    let config = LOYALTY_CONFIG.may_load(deps.storage)?;
+    let epoch = match &config {
+        Some(loyalty) => load_loyalty_epoch(deps.storage, &env, loyalty)?,
+        None => LoyaltyEpoch::default(),
+    };
+
+    Ok(LoyaltyResponse { config, epoch: epoch.epoch, epoch_minted: epoch.minted })
+}
+
+fn query_staking(deps: Deps, env: Env) -> StdResult<StakingResponse> {
+    let delegations = deps.querier.query_all_delegations(env.contract.address.clone())?
+        .into_iter()
+        .map(|delegation| {
+            let pending_rewards = deps.querier.query_delegation(env.contract.address.clone(), delegation.validator.clone())?
+                .map_or(Uint128::zero(), |full| get_juno_rewards(&full.accumulated_rewards));
+            Ok(DelegationResponse {
+                validator: delegation.validator,
+                amount: delegation.amount.amount,
+                pending_rewards,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(StakingResponse {
+        liquid_reserve: LIQUID_RESERVE.may_load(deps.storage)?.unwrap_or_default(),
+        liquid_balance: get_shared_juno_balance(deps.querier, deps.storage, &env)?,
+        delegations,
+    })
+}
+
+fn query_stats(deps: Deps) -> StdResult<StatsResponse> {
+    let stats = STATS.may_load(deps.storage)?.unwrap_or_default();
+
+    let load_totals = |totals: Map<&str, DenomTotal>| -> StdResult<Vec<DenomTotal>> {
+        totals
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(_, total)| total))
+            .collect()
+    };
+
+    let pool_volumes = POOL_VOLUMES
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|((pool, _), volume)| PoolVolume { pool, denom: volume.denom, amount: volume.amount }))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(StatsResponse {
+        total_trades: stats.total_trades,
+        unique_recipients: stats.unique_recipients,
+        volume: load_totals(VOLUME_BY_DENOM)?,
+        fees_accrued: load_totals(FEES_ACCRUED_BY_DENOM)?,
+        fees_withdrawn: load_totals(FEES_WITHDRAWN_BY_DENOM)?,
+        pool_volumes,
+    })
+}
+
+fn query_user_balances(deps: Deps, user: String) -> StdResult<UserBalancesResponse> {
+    let user = deps.api.addr_validate(&user)?;
+
+    let balances = USER_BALANCES
+        .prefix(user.clone())
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, balance)| DenomBalance { denom: balance.denom, amount: balance.amount }))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(UserBalancesResponse { user, balances })
+}
+
+fn query_intent_nonce(deps: Deps, user: String, nonce: u64) -> StdResult<IntentNonceResponse> {
+    let user = deps.api.addr_validate(&user)?;
+
+    Ok(IntentNonceResponse {
+        pubkey: INTENT_KEYS.may_load(deps.storage, user.clone())?,
+        used: INTENT_NONCES.has(deps.storage, (user, nonce)),
+    })
+}
@@ -41,4 +41,91 @@ pub enum ContractError {
 
     #[error("Cw20InputZero")]
     Cw20InputZero {},
+
+    #[error("Platform Fee Out Of Bounds")]
+    PlatformFeeOutOfBounds {},
+
+    #[error("Invalid Fee Config")]
+    InvalidFeeConfig {},
+
+    #[error("No Gas Reimbursement To Claim")]
+    NoGasReimbursement {},
+
+    #[error("Insufficient Fee Balance")]
+    InsufficientFeeBalance {},
+
+    #[error("Invalid Address: {field}")]
+    InvalidAddress { field: String },
+
+    #[error("Pool Not Registered")]
+    PoolNotRegistered {},
+
+    #[error("Pool Disabled")]
+    PoolDisabled {},
+
+    #[error("No Registered Pool For Pair")]
+    NoPoolForPair {},
+
+    #[error("Invalid Split")]
+    InvalidSplit {},
+
+    #[error("Unsupported Venue")]
+    UnsupportedVenue {},
+
+    #[error("Unsupported Denom")]
+    UnsupportedDenom {},
+
+    #[error("Insufficient User Balance")]
+    InsufficientUserBalance {},
+
+    #[error("Invalid Recipient")]
+    InvalidRecipient {},
+
+    #[error("Intent Key Not Set")]
+    IntentKeyNotSet {},
+
+    #[error("Invalid Signature")]
+    InvalidSignature {},
+
+    #[error("Intent Nonce Already Used")]
+    IntentReplayed {},
+
+    #[error("Intent Violated")]
+    IntentViolated {},
+
+    #[error("Unknown Reply Id")]
+    UnknownReplyId {},
+
+    #[error("Invalid Vesting")]
+    InvalidVesting {},
+
+    #[error("Nothing To Claim")]
+    NothingToClaim {},
+
+    #[error("Buyback Not Configured")]
+    BuybackNotConfigured {},
+
+    #[error("Liquidity Not Configured")]
+    LiquidityNotConfigured {},
+
+    #[error("Liquid Reserve Breached")]
+    LiquidReserveBreached {},
+
+    #[error("Unknown Validator")]
+    UnknownValidator {},
+
+    #[error("No Delegation")]
+    NoDelegation {},
+
+    #[error("Loyalty Not Configured")]
+    LoyaltyNotConfigured {},
+
+    #[error("Invalid Loyalty Config")]
+    InvalidLoyaltyConfig {},
+
+    #[error("Contract Not Minter")]
+    ContractNotMinter {},
+
+    #[error("Invalid Timeout")]
+    InvalidTimeout {},
 }
@@ -0,0 +1,62 @@
+use cosmwasm_std::{Addr, Event, Uint128};
+use cw20::Denom;
+
+use crate::state::Trade;
+
+// wasmd prefixes custom event types with "wasm-", so these are indexed as wasm-buy, wasm-sweep, ...
+pub const BUY_EVENT: &str = "buy";
+pub const SWEEP_EVENT: &str = "sweep";
+pub const FEE_WITHDRAW_EVENT: &str = "fee-withdraw";
+pub const BOT_ROLE_EVENT: &str = "bot-role";
+pub const ADMIN_CHANGE_EVENT: &str = "admin-change";
+
+/// Native denoms are written as is, CW20s as their contract address
+pub fn denom_attribute(denom: &Denom) -> String {
+    match denom {
+        Denom::Native(native_str) => native_str.clone(),
+        Denom::Cw20(cw20_address) => cw20_address.to_string(),
+    }
+}
+
+fn trade_event(ty: &str, trade: &Trade) -> Event {
+    Event::new(ty)
+        .add_attribute("trade_id", trade.id.to_string())
+        .add_attribute("bot", trade.bot.clone())
+        .add_attribute("recipient", trade.recipient.clone())
+        .add_attribute("pool", trade.pool.clone())
+        .add_attribute("input_denom", denom_attribute(&trade.input_denom))
+        .add_attribute("input_amount", trade.input_amount)
+        .add_attribute("output_denom", denom_attribute(&trade.output_denom))
+        .add_attribute("quoted_output", trade.quoted_output)
+        .add_attribute("amount_out_min", trade.amount_out_min)
+}
+
+pub fn buy_event(trade: &Trade) -> Event {
+    trade_event(BUY_EVENT, trade)
+        .add_attribute("platform_fee", trade.platform_fee)
+        .add_attribute("gas_reimbursement", trade.gas_reimbursement)
+}
+
+pub fn sweep_event(trade: &Trade) -> Event {
+    trade_event(SWEEP_EVENT, trade)
+}
+
+pub fn fee_withdraw_event(to: &Addr, denom: &Denom, amount: Uint128, pending_platform_fee: Uint128) -> Event {
+    Event::new(FEE_WITHDRAW_EVENT)
+        .add_attribute("recipient", to.clone())
+        .add_attribute("denom", denom_attribute(denom))
+        .add_attribute("amount", amount)
+        .add_attribute("pending_platform_fee", pending_platform_fee)
+}
+
+pub fn bot_role_event(bot: &Addr, enabled: bool) -> Event {
+    Event::new(BOT_ROLE_EVENT)
+        .add_attribute("bot", bot.clone())
+        .add_attribute("enabled", enabled.to_string())
+}
+
+pub fn admin_change_event(previous_admin: &Addr, new_admin: &Addr) -> Event {
+    Event::new(ADMIN_CHANGE_EVENT)
+        .add_attribute("previous_admin", previous_admin.clone())
+        .add_attribute("new_admin", new_admin.clone())
+}
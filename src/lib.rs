@@ -1,5 +1,7 @@
 pub mod contract;
-mod error;
+pub mod error;
+pub mod events;
 pub mod msg;
 pub mod state;
 pub mod util;
+pub mod venue;
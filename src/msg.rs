@@ -1,9 +1,17 @@
-use cosmwasm_std::{Addr, Uint128, Uint64, Coin};
+use cosmwasm_std::{Addr, Binary, Decimal, Uint128, Uint64, Coin};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use cw20::{Cw20ReceiveMsg, Denom};
+
+use crate::state::{BuybackConfig, DenomTotal, HolderDiscountConfig, HolderDiscountTier, LiquidityConfig, LoyaltyConfig, FeeTier, IbcSend, Trade, Vesting};
+use crate::venue::Venue;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
+    pub default_fee_bips: Uint128,
+    pub max_fee_bips: Uint128,
+    pub gas_price: Decimal,
+    pub max_gas: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -11,37 +19,398 @@ pub struct InstantiateMsg {
 pub enum ExecuteMsg {
     WithdrawFee {
         // release some coins - if quantity is None, release all coins in balance
-        to: Addr,
+        to: String,
         amount: Uint128,
     },
     SetAdmin {
-        new_admin: Addr,
+        new_admin: String,
     },
     SetBotRole {
-        new_bot: Addr,
+        new_bot: String,
         enabled: bool
     },
     BuyToken { 
         juno_amount: Uint128
         , token_amount_per_native: Uint128
         , slippage_bips: Uint128
-        , recipient: Addr
-        , pool_address: Addr
-        , platform_fee_bips: Uint128
+        , recipient: String
+        , pool_address: String
+        // if None the fee schedule decides, otherwise it must lie between the scheduled bips and the max
+        , platform_fee_bips: Option<Uint128>
         , gas_estimate: Uint128
         , deadline: Uint64
+        // a buy the user signed off-chain, juno_amount is debited from the intent user's deposit instead of the shared balance.
+        // Deposits are only ever spent through an intent
+        , intent: Option<Box<SignedBuyIntent>>
+        // if set, the output is swapped into this contract and handed to the recipient contract with a PurchaseHookMsg
+        , callback: Option<Binary>
+    },
+    /// Same as BuyToken, but swaps through whichever registered pool for ujuno/target_denom quotes the most output
+    BuyTokenBestPool { 
+        juno_amount: Uint128
+        , token_amount_per_native: Uint128
+        , slippage_bips: Uint128
+        , recipient: String
+        , target_denom: Denom
+        , platform_fee_bips: Option<Uint128>
+        , gas_estimate: Uint128
+        , deadline: Uint64
+    },
+    /// Same as BuyTokenBestPool, but spreads the input over up to max_pools pools to limit price impact.
+    /// Unlike the other buys it sets no response data, its trades are in the buy events
+    BuyTokenSplit { 
+        juno_amount: Uint128
+        , token_amount_per_native: Uint128
+        , slippage_bips: Uint128
+        , recipient: String
+        , target_denom: Denom
+        , max_pools: u32
+        , platform_fee_bips: Option<Uint128>
+        , gas_estimate: Uint128
+        , deadline: Uint64
+    },
+    /// Buys exactly desired_output of the pool's other token with the ujuno an enabled bot attached, refunding what is left over
+    BuyTokenExactOut {
+        desired_output: Uint128,
+        recipient: String,
+        pool_address: String,
+        platform_fee_bips: Option<Uint128>,
+        gas_estimate: Uint128,
+        deadline: Uint64,
     },
     SwapAtomToJuno {
-    }
+    },
+    SetFeeConfig {
+        default_bips: Uint128,
+        max_bips: Uint128,
+        tiers: Vec<FeeTier>,
+    },
+    SetFeeOverride {
+        recipient: String,
+        /// None removes the override
+        fee_bips: Option<Uint128>,
+    },
+    /// Discounts the scheduled platform fee of recipients holding the token or an NFT of the collection.
+    /// The largest applicable discount wins, overridden recipients pay their override
+    SetHolderDiscount {
+        token: Option<String>,
+        tiers: Vec<HolderDiscountTier>,
+        nft_collection: Option<String>,
+        nft_discount_bips: Uint128,
+    },
+    SetGasConfig {
+        gas_price: Decimal,
+        max_gas: Uint128,
+    },
+    /// Pays the caller its unclaimed gas reimbursements in ujuno, even after its bot role is disabled
+    ClaimGasReimbursement {},
+    /// Adds a pool holding ujuno to the registry, or refreshes its cached denoms. Venue defaults to wasmswap
+    RegisterPool {
+        pool_address: String,
+        venue: Option<Venue>,
+    },
+    SetPoolEnabled {
+        pool_address: String,
+        enabled: bool,
+    },
+    /// Credits the attached ujuno to the sender's internal balance
+    Deposit {},
+    /// Receives CW20s sent with a ReceiveMsg
+    Receive(Cw20ReceiveMsg),
+    /// Pays out part of the sender's internal balance
+    Withdraw {
+        denom: Denom,
+        amount: Uint128,
+    },
+    /// Sets the secp256k1 public key (compressed or uncompressed) the sender signs buy intents with
+    SetIntentKey {
+        pubkey: Binary,
+    },
+    /// Same as BuyToken, but the output stays in the contract and vests linearly to the recipient
+    /// over duration seconds, nothing being claimable during the first cliff seconds
+    BuyTokenVested {
+        juno_amount: Uint128,
+        token_amount_per_native: Uint128,
+        slippage_bips: Uint128,
+        recipient: String,
+        pool_address: String,
+        platform_fee_bips: Option<Uint128>,
+        gas_estimate: Uint128,
+        deadline: Uint64,
+        cliff: u64,
+        duration: u64,
+    },
+    /// Pays the sender everything vested to them and not yet claimed
+    ClaimVested {},
+    /// Sets the wasmswap pool of ujuno against the project CW20 that fees are bought back through
+    SetBuybackConfig {
+        pool_address: String,
+        max_slippage_bips: Uint128,
+    },
+    /// Spends amount ujuno of the pending platform fee on the project CW20 and burns it.
+    /// Open to the admin and enabled bots
+    BuybackAndBurn {
+        amount: Uint128,
+    },
+    /// Sets the wasmswap pool fees are compounded into
+    SetLiquidityConfig {
+        pool_address: String,
+        max_slippage_bips: Uint128,
+    },
+    /// Swaps half of amount ujuno of the pending platform fee to the pool's other token
+    /// and adds both halves to the configured pool, ujuno the deposit leaves over goes back to the pending platform fee
+    /// and the other token it leaves over stays in the contract
+    CompoundFeesToLiquidity {
+        amount: Uint128,
+    },
+    /// Burns amount of the contract's LP tokens and sends the tokens the pool pays out to `to`
+    RemoveLiquidity {
+        amount: Uint128,
+        min_token1: Uint128,
+        min_token2: Uint128,
+        to: String,
+    },
+    /// Sets the loyalty CW20 minted on buy_token intents the recipient funds. The contract has to be its
+    /// minter and points_per_ujuno * ujuno_per_point may not exceed the max platform fee
+    SetLoyaltyConfig {
+        token: String,
+        points_per_ujuno: Decimal,
+        ujuno_per_point: Decimal,
+        epoch_seconds: u64,
+        epoch_cap: Uint128,
+    },
+    /// Sets the ujuno Delegate has to leave liquid for buys and fee withdrawals
+    SetLiquidReserve {
+        amount: Uint128,
+    },
+    Delegate {
+        validator: String,
+        amount: Uint128,
+    },
+    Undelegate {
+        validator: String,
+        amount: Uint128,
+    },
+    Redelegate {
+        src_validator: String,
+        dst_validator: String,
+        amount: Uint128,
+    },
+    /// Withdraws the rewards of one validator, or of every delegation if None, into the fee ledger
+    ClaimStakingRewards {
+        validator: Option<String>,
+    },
+    /// Buys with the ujuno attached and sends the output, which must be a native denom, to
+    /// remote_recipient over the ICS20 channel. The transfer asks ibc-hooks for a callback, if it
+    /// fails or times out the output is credited to the sender's internal balance.
+    BuyTokenAndIbcSend {
+        token_amount_per_native: Uint128,
+        slippage_bips: Uint128,
+        pool_address: String,
+        platform_fee_bips: Option<Uint128>,
+        deadline: Uint64,
+        channel: String,
+        remote_recipient: String,
+        /// Seconds from now until the transfer times out, at most a week
+        timeout: u64,
+    },
+}
+
+/// Executed on a recipient contract when a BuyToken with a callback settles. Native outputs are
+/// attached as funds, CW20 outputs arrive through Cw20ExecuteMsg::Send with this as its msg.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PurchaseHookMsg {
+    ReceivePurchase {
+        /// The user whose balance paid, or the bot
+        buyer: String,
+        amount: Uint128,
+        denom: Denom,
+        msg: Binary,
+    },
+}
+
+/// Lifecycle callbacks of the ibc-hooks module for transfers this contract sent
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoMsg {
+    #[serde(rename = "ibc_lifecycle_complete")]
+    IbcLifecycleComplete(IbcLifecycleComplete),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IbcLifecycleComplete {
+    #[serde(rename = "ibc_ack")]
+    IbcAck {
+        channel: String,
+        sequence: u64,
+        ack: String,
+        success: bool,
+    },
+    #[serde(rename = "ibc_timeout")]
+    IbcTimeout {
+        channel: String,
+        sequence: u64,
+    },
+}
+
+/// What a user allows a bot to buy from their internal balance
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BuyIntent {
+    pub user: String,
+    pub max_juno_amount: Uint128,
+    /// Lower bound on BuyToken's token_amount_per_native
+    pub min_token_amount_per_native: Uint128,
+    /// Upper bound on BuyToken's slippage_bips, together with the above it floors the output
+    pub max_slippage_bips: Uint128,
+    /// Upper bound on the platform fee bips charged, whether the bot supplied them or the schedule did
+    pub max_platform_fee_bips: Uint128,
+    /// Upper bound on the gas reimbursement the bot takes out of juno_amount
+    pub max_gas_reimbursement: Uint128,
+    pub recipient: String,
+    /// The only pool the buy may swap through
+    pub pool_address: String,
+    /// Any value not used in an earlier intent of the same user
+    pub nonce: u64,
+    /// Block time in seconds after which the intent is void
+    pub expiry: Uint64,
+}
+
+/// `signature` is the 64 byte secp256k1 signature of sha256(contract address || JSON of the intent),
+/// see `util::intent_hash`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SignedBuyIntent {
+    pub intent: BuyIntent,
+    pub signature: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    /// Credits the CW20s to the sender's internal balance. Only tokens of registered pools are accepted
+    Deposit {},
+    /// Burns the loyalty points sent and rebates their ujuno value from the platform fee ledger
+    RedeemPoints {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    /// Returns a human-readable representation of the arbiter.
+    /// Returns admin, fees, block time and balances in one response.
+    /// Kept for existing clients, prefer Config, Balances and Status.
     GetInfos {
         token: String,
-    },    
+    },
+    /// Returns the admin and the fee and gas settings
+    Config {},
+    /// Returns the contract's balance of each denom, native or CW20
+    Balances {
+        denoms: Vec<Denom>,
+    },
+    /// Returns the block the query ran at and the contract's ledgers
+    Status {},
+    /// Returns the admin-managed fee schedule
+    FeeSchedule {},
+    /// Returns the bips `BuyToken` would charge for this recipient
+    RecipientFee {
+        recipient: String,
+    },
+    /// Returns the gas reimbursements accrued and claimed by a bot
+    GasReimbursement {
+        bot: String,
+    },
+    /// Returns the registered pools ordered by address
+    ListPools {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Runs BuyToken's checks and fee/gas math against the pool's current price without trading.
+    /// The bot role is only checked when `bot` is given, the deposit and nonce only when `intent` is.
+    SimulateBuy {
+        juno_amount: Uint128,
+        token_amount_per_native: Uint128,
+        slippage_bips: Uint128,
+        recipient: String,
+        pool: String,
+        platform_fee_bips: Option<Uint128>,
+        gas_estimate: Uint128,
+        deadline: Uint64,
+        bot: Option<String>,
+        intent: Option<Box<SignedBuyIntent>>,
+    },
+    /// Returns the ujuno input, fee and gas needed to buy exactly desired_output from a wasmswap pool
+    SimulateBuyExactOut {
+        desired_output: Uint128,
+        pool: String,
+        recipient: String,
+        platform_fee_bips: Option<Uint128>,
+        gas_estimate: Uint128,
+    },
+    /// Returns the trades delivered to a recipient, oldest first
+    TradesByRecipient {
+        recipient: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns the trades executed by a bot, oldest first
+    TradesByBot {
+        bot: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns the trades routed through a pool, oldest first
+    TradesByPool {
+        pool: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns the running totals kept across buys, sweeps and fee withdrawals
+    Stats {},
+    /// Returns the internal balances a user has deposited
+    UserBalances {
+        user: String,
+    },
+    /// Returns the user's intent key and whether the nonce was already used
+    IntentNonce {
+        user: String,
+        nonce: u64,
+    },
+    /// Returns the recipient's vestings ordered by id
+    Vestings {
+        recipient: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns the holder discount token, collection and tiers, if configured
+    HolderDiscount {},
+    /// Returns the buyback pool and token and the total burned so far
+    Buyback {},
+    /// Returns the loyalty token and rates and the points minted in the current epoch
+    Loyalty {},
+    /// Returns the LP tokens the contract holds in the configured pool and their share of its reserves
+    LiquidityPosition {},
+    /// Returns the contract's delegations with their pending rewards and the liquid reserve
+    Staking {},
+    /// Returns the IBC transfer still awaiting its ack or timeout, if any
+    IbcTransfer {
+        channel: String,
+        sequence: u64,
+    },
+}
+
+/// Set as the response data of BuyToken, BuyTokenBestPool, BuyTokenExactOut, BuyTokenVested and SwapAtomToJuno.
+/// BuyTokenSplit records one trade per pool and BuyTokenAndIbcSend ends in a transfer, neither sets data.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BuyTokenResponse {
+    pub trade_id: u64,
+    pub input_denom: Denom,
+    pub input_amount: Uint128,
+    pub platform_fee: Uint128,
+    pub output_denom: Denom,
+    pub quoted_output: Uint128,
+    /// Measured from the recipient's balance once the swap ran
+    pub actual_output: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -55,6 +424,200 @@ pub struct AdminResponse {
     //pub all_tokens: Vec<Coin>
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub admin: Addr,
+    pub contract_address: Addr,
+    pub default_fee_bips: Uint128,
+    pub max_fee_bips: Uint128,
+    pub gas_price: Decimal,
+    pub max_gas: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DenomBalance {
+    pub denom: Denom,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BalancesResponse {
+    pub balances: Vec<DenomBalance>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingResponse {
+    pub vesting: Vesting,
+    pub vested: Uint128,
+    pub claimable: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingsResponse {
+    pub recipient: Addr,
+    pub vestings: Vec<VestingResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LoyaltyResponse {
+    pub config: Option<LoyaltyConfig>,
+    pub epoch: u64,
+    pub epoch_minted: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HolderDiscountResponse {
+    pub config: Option<HolderDiscountConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BuybackResponse {
+    pub config: Option<BuybackConfig>,
+    pub total_burned: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidityPositionResponse {
+    pub config: LiquidityConfig,
+    pub lp_balance: Uint128,
+    pub lp_token_supply: Uint128,
+    pub token1_denom: Denom,
+    pub token1_amount: Uint128,
+    pub token2_denom: Denom,
+    pub token2_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DelegationResponse {
+    pub validator: String,
+    pub amount: Uint128,
+    pub pending_rewards: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakingResponse {
+    pub liquid_reserve: Uint128,
+    pub liquid_balance: Uint128,
+    pub delegations: Vec<DelegationResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcTransferResponse {
+    pub transfer: Option<IbcSend>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IntentNonceResponse {
+    pub pubkey: Option<Binary>,
+    pub used: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UserBalancesResponse {
+    pub user: Addr,
+    pub balances: Vec<DenomBalance>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StatusResponse {
+    pub block_height: u64,
+    pub blocktime: u64,
+    pub pending_platform_fee: Uint128,
+    pub pending_gas_reimbursement: Uint128,
+    pub juno_balance: Uint128,
+    /// ujuno deposited by users
+    pub user_juno_deposits: Uint128,
+    /// What WithdrawFee can currently pay out: the fee ledger, bounded by the ujuno not owed to bots or users
+    pub withdrawable_fee: Uint128,
+    pub trade_count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeScheduleResponse {
+    pub default_bips: Uint128,
+    pub max_bips: Uint128,
+    pub tiers: Vec<FeeTier>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RecipientFeeResponse {
+    pub fee_bips: Uint128,
+    pub volume: Uint128,
+    pub overridden: bool,
+    /// Holder discount already applied to fee_bips
+    pub discount_bips: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GasReimbursementResponse {
+    pub bot: Addr,
+    pub accrued: Uint128,
+    pub claimed: Uint128,
+    pub claimable: Uint128,
+    pub gas_price: Decimal,
+    pub max_gas: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolResponse {
+    pub pool_address: Addr,
+    pub token1_denom: Denom,
+    pub token2_denom: Denom,
+    pub enabled: bool,
+    pub venue: Venue,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolsResponse {
+    pub pools: Vec<PoolResponse>,
+}
+
+/// `error` holds the error BuyToken would fail with, in which case the amounts are zero
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateBuyResponse {
+    pub expected_output: Uint128,
+    pub amount_out_min: Uint128,
+    pub output_denom: Option<Denom>,
+    pub platform_fee: Uint128,
+    pub gas_reimbursement: Uint128,
+    pub swap_amount: Uint128,
+    pub error: Option<String>,
+}
+
+/// `error` holds the error BuyTokenExactOut would fail with, in which case the amounts are zero
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateBuyExactOutResponse {
+    pub juno_amount: Uint128,
+    pub platform_fee: Uint128,
+    pub gas_reimbursement: Uint128,
+    pub swap_amount: Uint128,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TradesResponse {
+    pub trades: Vec<Trade>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolVolume {
+    pub pool: Addr,
+    pub denom: Denom,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StatsResponse {
+    pub total_trades: u64,
+    pub unique_recipients: u64,
+    /// Trade input volume per input denom
+    pub volume: Vec<DenomTotal>,
+    pub fees_accrued: Vec<DenomTotal>,
+    pub fees_withdrawn: Vec<DenomTotal>,
+    /// Trade input volume per pool and input denom
+    pub pool_volumes: Vec<PoolVolume>,
+}
+
 pub struct BotsResponse {
     pub admin: String,
 }
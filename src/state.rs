@@ -1,20 +1,358 @@
-use cosmwasm_std::{Addr, Storage, Uint128, };
+use cosmwasm_std::{Addr, Binary, Decimal, Storage, Uint128, };
 use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cw_storage_plus::{Map};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
+use cw20::Denom;
+
+use crate::venue::Venue;
 
 static CONFIG_KEY: &[u8] = b"config";
 
 pub const BOT_KEY: &str = "bot_role";
 pub const BOT_ROLES: Map<Addr, bool> = Map::new(BOT_KEY);
 
+pub const FEE_CONFIG_KEY: &str = "fee_config";
+pub const FEE_CONFIG: Item<FeeConfig> = Item::new(FEE_CONFIG_KEY);
+
+pub const HOLDER_DISCOUNT_KEY: &str = "holder_discount";
+pub const HOLDER_DISCOUNT: Item<HolderDiscountConfig> = Item::new(HOLDER_DISCOUNT_KEY);
+
+pub const FEE_OVERRIDE_KEY: &str = "fee_override";
+pub const FEE_OVERRIDES: Map<Addr, Uint128> = Map::new(FEE_OVERRIDE_KEY);
+
+pub const RECIPIENT_VOLUME_KEY: &str = "recipient_volume";
+pub const RECIPIENT_VOLUMES: Map<Addr, Uint128> = Map::new(RECIPIENT_VOLUME_KEY);
+
+pub const GAS_CONFIG_KEY: &str = "gas_config";
+pub const GAS_CONFIG: Item<GasConfig> = Item::new(GAS_CONFIG_KEY);
+
+pub const GAS_LEDGER_KEY: &str = "gas_ledger";
+pub const GAS_LEDGERS: Map<Addr, GasLedger> = Map::new(GAS_LEDGER_KEY);
+
+pub const PENDING_GAS_KEY: &str = "pending_gas";
+pub const PENDING_GAS_REIMBURSEMENT: Item<Uint128> = Item::new(PENDING_GAS_KEY);
+
+pub const POOL_KEY: &str = "pool";
+pub const POOLS: Map<Addr, PoolInfo> = Map::new(POOL_KEY);
+
+pub const TRADE_COUNT_KEY: &str = "trade_count";
+pub const TRADE_COUNT: Item<u64> = Item::new(TRADE_COUNT_KEY);
+
+pub const STATS_KEY: &str = "stats";
+pub const STATS: Item<Stats> = Item::new(STATS_KEY);
+
+/// Keyed by `denom_key`
+pub const VOLUME_BY_DENOM: Map<&str, DenomTotal> = Map::new("volume_by_denom");
+pub const FEES_ACCRUED_BY_DENOM: Map<&str, DenomTotal> = Map::new("fees_accrued_by_denom");
+pub const FEES_WITHDRAWN_BY_DENOM: Map<&str, DenomTotal> = Map::new("fees_withdrawn_by_denom");
+
+/// Input volume of the trades routed through each pool, keyed by pool and `denom_key` of the input
+pub const POOL_VOLUMES: Map<(Addr, String), DenomTotal> = Map::new("pool_denom_volume");
+
+/// Funds users deposited, keyed by user and `denom_key`
+pub const USER_BALANCE_KEY: &str = "user_balance";
+pub const USER_BALANCES: Map<(Addr, String), DenomTotal> = Map::new(USER_BALANCE_KEY);
+
+/// Sum of the user balances per `denom_key`, held by the contract but not its to spend
+pub const USER_BALANCE_TOTAL_KEY: &str = "user_balance_total";
+pub const USER_BALANCE_TOTALS: Map<&str, Uint128> = Map::new(USER_BALANCE_TOTAL_KEY);
+
+/// The IBC buy whose swap reply is outstanding
+pub const PENDING_IBC_SEND_KEY: &str = "pending_ibc_send";
+pub const PENDING_IBC_SEND: Item<IbcSend> = Item::new(PENDING_IBC_SEND_KEY);
+
+/// IBC transfers awaiting their ack or timeout, keyed by source channel and packet sequence
+pub const IBC_TRANSFER_KEY: &str = "ibc_transfer";
+pub const IBC_TRANSFERS: Map<(String, u64), IbcSend> = Map::new(IBC_TRANSFER_KEY);
+
+/// The buy or sweep whose swap reply is outstanding
+pub const PENDING_OUTPUT_KEY: &str = "pending_output";
+pub const PENDING_OUTPUT: Item<PendingOutput> = Item::new(PENDING_OUTPUT_KEY);
+
+pub const VESTING_COUNT_KEY: &str = "vesting_count";
+pub const VESTING_COUNT: Item<u64> = Item::new(VESTING_COUNT_KEY);
+
+/// Vestings keyed by recipient and vesting id
+pub const VESTING_KEY: &str = "vesting";
+pub const VESTINGS: Map<(Addr, u64), Vesting> = Map::new(VESTING_KEY);
+
+/// Unclaimed vested outputs per `denom_key`, held by the contract for the recipients
+pub const VESTING_TOTAL_KEY: &str = "vesting_total";
+pub const VESTING_TOTALS: Map<&str, Uint128> = Map::new(VESTING_TOTAL_KEY);
+
+pub const BUYBACK_CONFIG_KEY: &str = "buyback_config";
+pub const BUYBACK_CONFIG: Item<BuybackConfig> = Item::new(BUYBACK_CONFIG_KEY);
+
+/// The contract's balance of the buyback token before the outstanding buyback swap
+pub const PENDING_BURN_KEY: &str = "pending_burn";
+pub const PENDING_BURN: Item<Uint128> = Item::new(PENDING_BURN_KEY);
+
+pub const TOTAL_BURNED_KEY: &str = "total_burned";
+pub const TOTAL_BURNED: Item<Uint128> = Item::new(TOTAL_BURNED_KEY);
+
+pub const LIQUIDITY_CONFIG_KEY: &str = "liquidity_config";
+pub const LIQUIDITY_CONFIG: Item<LiquidityConfig> = Item::new(LIQUIDITY_CONFIG_KEY);
+
+/// The compound whose swap reply is outstanding
+pub const PENDING_COMPOUND_KEY: &str = "pending_compound";
+pub const PENDING_COMPOUND: Item<PendingCompound> = Item::new(PENDING_COMPOUND_KEY);
+
+/// The liquidity removal whose reply is outstanding
+pub const PENDING_REMOVE_LIQUIDITY_KEY: &str = "pending_remove_liquidity";
+pub const PENDING_REMOVE_LIQUIDITY: Item<PendingRemoveLiquidity> = Item::new(PENDING_REMOVE_LIQUIDITY_KEY);
+
+pub const LOYALTY_CONFIG_KEY: &str = "loyalty_config";
+pub const LOYALTY_CONFIG: Item<LoyaltyConfig> = Item::new(LOYALTY_CONFIG_KEY);
+
+pub const LOYALTY_EPOCH_KEY: &str = "loyalty_epoch";
+pub const LOYALTY_EPOCH: Item<LoyaltyEpoch> = Item::new(LOYALTY_EPOCH_KEY);
+
+/// ujuno that has to stay liquid, on top of the gas owed to bots, when delegating
+pub const LIQUID_RESERVE_KEY: &str = "liquid_reserve";
+pub const LIQUID_RESERVE: Item<Uint128> = Item::new(LIQUID_RESERVE_KEY);
+
+/// secp256k1 public key each user signs buy intents with
+pub const INTENT_KEY_KEY: &str = "intent_key";
+pub const INTENT_KEYS: Map<Addr, Binary> = Map::new(INTENT_KEY_KEY);
+
+/// Intent nonces already executed per user
+pub const INTENT_NONCE_KEY: &str = "intent_nonce";
+pub const INTENT_NONCES: Map<(Addr, u64), bool> = Map::new(INTENT_NONCE_KEY);
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
     pub owner: Addr,
     pub pending_platform_fee: Uint128,
 }
 
+/// A volume tier: recipients whose cumulative ujuno volume reaches `min_volume`
+/// are charged `fee_bips` instead of the default.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeTier {
+    pub min_volume: Uint128,
+    pub fee_bips: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HolderDiscountTier {
+    pub min_balance: Uint128,
+    /// Share of the scheduled fee waived, in bips
+    pub discount_bips: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HolderDiscountConfig {
+    /// CW20 whose balance is matched against `tiers`
+    pub token: Option<Addr>,
+    /// Sorted by ascending `min_balance`
+    pub tiers: Vec<HolderDiscountTier>,
+    /// Owning any NFT of this cw721 collection grants `nft_discount_bips`
+    pub nft_collection: Option<Addr>,
+    pub nft_discount_bips: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeConfig {
+    pub default_bips: Uint128,
+    pub max_bips: Uint128,
+    /// Sorted by ascending `min_volume`
+    pub tiers: Vec<FeeTier>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GasConfig {
+    /// ujuno paid per unit of gas
+    pub gas_price: Decimal,
+    pub max_gas: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct GasLedger {
+    pub accrued: Uint128,
+    pub claimed: Uint128,
+}
+
+/// A pool the admin allows `BuyToken` to trade against, with its denoms cached at registration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolInfo {
+    pub token1_denom: Denom,
+    pub token2_denom: Denom,
+    pub enabled: bool,
+    #[serde(default)]
+    pub venue: Venue,
+}
+
+/// An executed buy or ATOM sweep. Amounts are as quoted when the swap messages were built.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Trade {
+    pub id: u64,
+    pub bot: Addr,
+    pub recipient: Addr,
+    pub pool: Addr,
+    pub block_height: u64,
+    pub block_time: u64,
+    pub input_denom: Denom,
+    pub input_amount: Uint128,
+    pub platform_fee: Uint128,
+    pub gas_reimbursement: Uint128,
+    pub quoted_output: Uint128,
+    pub amount_out_min: Uint128,
+    pub output_denom: Denom,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct Stats {
+    pub total_trades: u64,
+    pub unique_recipients: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DenomTotal {
+    pub denom: Denom,
+    pub amount: Uint128,
+}
+
+pub fn denom_key(denom: &Denom) -> String {
+    match denom {
+        Denom::Native(native_str) => format!("native:{}", native_str),
+        Denom::Cw20(cw20_address) => format!("cw20:{}", cw20_address),
+    }
+}
+
+/// `holder`'s balance of the trade's output before its swap ran
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingOutput {
+    pub trade_id: u64,
+    pub holder: Addr,
+    pub balance_before: Uint128,
+    /// Set when the contract holds the output and passes it on to the trade's recipient
+    pub callback: Option<PurchaseCallback>,
+    /// Set when the contract holds the output in a vesting for the trade's recipient
+    pub vesting: Option<VestingTerms>,
+}
+
+/// Fees are bought back through `pool`, a wasmswap pool of ujuno against the CW20 `token`,
+/// accepting at most `max_slippage_bips` less than the pool's quote
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BuybackConfig {
+    pub pool: Addr,
+    pub token: Addr,
+    pub max_slippage_bips: Uint128,
+}
+
+/// Fees are compounded into `pool`, a wasmswap pool with ujuno on one side, minting at most
+/// `max_slippage_bips` fewer LP tokens than the reserves promise
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidityConfig {
+    pub pool: Addr,
+    pub lp_token: Addr,
+    pub max_slippage_bips: Uint128,
+}
+
+/// Fees taken off the ledger for a compound, of which `juno_left` wait for the swap of the rest
+/// into `token_denom`. `balance_before` is the contract's balance of that token before the swap.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingCompound {
+    pub amount: Uint128,
+    pub juno_left: Uint128,
+    pub token_denom: Denom,
+    pub balance_before: Uint128,
+}
+
+/// The contract's balances of both pool tokens before a liquidity removal that pays `to`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingRemoveLiquidity {
+    pub to: Addr,
+    pub token1_denom: Denom,
+    pub token1_balance_before: Uint128,
+    pub token2_denom: Denom,
+    pub token2_balance_before: Uint128,
+}
+
+/// Loyalty CW20 minted by the contract on buy_token intents the recipient funds and redeemable against the fee ledger
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LoyaltyConfig {
+    pub token: Addr,
+    /// Points minted per ujuno spent
+    pub points_per_ujuno: Decimal,
+    /// ujuno rebated per point redeemed
+    pub ujuno_per_point: Decimal,
+    pub epoch_seconds: u64,
+    /// Points mintable across all recipients within one epoch
+    pub epoch_cap: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct LoyaltyEpoch {
+    pub epoch: u64,
+    pub minted: Uint128,
+}
+
+/// Seconds from the purchase until the cliff and until everything has vested
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingTerms {
+    pub cliff: u64,
+    pub duration: u64,
+}
+
+/// Output of a vested buy, unlocking linearly from `start` to `end` with nothing claimable before `cliff`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Vesting {
+    pub id: u64,
+    pub trade_id: u64,
+    pub denom: Denom,
+    pub total: Uint128,
+    pub claimed: Uint128,
+    pub start: u64,
+    pub cliff: u64,
+    pub end: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PurchaseCallback {
+    pub buyer: Addr,
+    pub msg: Binary,
+}
+
+/// A buy delivered over IBC. `amount` is the swap output measured against `balance_before`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcSend {
+    pub trade_id: u64,
+    pub user: Addr,
+    pub channel: String,
+    pub remote_recipient: String,
+    /// Block time in seconds the transfer times out at
+    pub timeout: u64,
+    pub denom: String,
+    pub balance_before: Uint128,
+    pub amount: Uint128,
+}
+
+pub struct TradeIndexes<'a> {
+    pub bot: MultiIndex<'a, Addr, Trade, u64>,
+    pub recipient: MultiIndex<'a, Addr, Trade, u64>,
+    pub pool: MultiIndex<'a, Addr, Trade, u64>,
+}
+
+impl<'a> IndexList<Trade> for TradeIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Trade>> + '_> {
+        let v: Vec<&dyn Index<Trade>> = vec![&self.bot, &self.recipient, &self.pool];
+        Box::new(v.into_iter())
+    }
+}
+
+pub const TRADE_KEY: &str = "trade";
+
+pub fn trades<'a>() -> IndexedMap<'a, u64, Trade, TradeIndexes<'a>> {
+    let indexes = TradeIndexes {
+        bot: MultiIndex::new(|trade: &Trade| trade.bot.clone(), TRADE_KEY, "trade__bot"),
+        recipient: MultiIndex::new(|trade: &Trade| trade.recipient.clone(), TRADE_KEY, "trade__recipient"),
+        pool: MultiIndex::new(|trade: &Trade| trade.pool.clone(), TRADE_KEY, "trade__pool"),
+    };
+    IndexedMap::new(TRADE_KEY, indexes)
+}
+
 pub fn config(storage: &mut dyn Storage) -> Singleton<State> {
     singleton(storage, CONFIG_KEY)
 }
@@ -1,9 +1,15 @@
 use cosmwasm_std::{
-    to_binary, Uint128, Coin, BankMsg,
+    to_binary, to_vec, Binary, Uint128, Uint256, Coin, BankMsg,
     WasmMsg, WasmQuery, QueryRequest, Addr, CosmosMsg,  QuerierWrapper, AllBalanceResponse, BalanceResponse as NativeBalanceResponse, BankQuery, StdError
 };
-use cw20::{Balance, Cw20ExecuteMsg, Denom, BalanceResponse as CW20BalanceResponse, Cw20QueryMsg};
+use cw20::{Balance, Cw20ExecuteMsg, Denom, BalanceResponse as CW20BalanceResponse, Cw20QueryMsg, MinterResponse};
 use crate::error::ContractError;
+use crate::msg::BuyIntent;
+use crate::state::PoolInfo;
+use crate::venue::Venue;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
 
 use wasmswap::msg::{ExecuteMsg as WasmswapExecuteMsg, QueryMsg as WasmswapQueryMsg, Token1ForToken2PriceResponse, Token2ForToken1PriceResponse, InfoResponse as WasmswapInfoResponse, TokenSelect};
 
@@ -14,28 +20,66 @@ pub const MAX_ORDER: u64 = 10;
 // pub fn multiple() -> Uint128 { Uint128::from(100u128) }
 // pub fn decimal() -> Uint128 { Uint128::from(1000000u128) }
 
-pub fn check_token_and_pool (
+pub fn get_pool_info(
     querier: QuerierWrapper,
-    denom: Denom,
     pool_address: Addr,
-) -> Result<bool, ContractError> {
+) -> Result<WasmswapInfoResponse, ContractError> {
     let pool_info_response: WasmswapInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-        contract_addr: pool_address.clone().into(),
+        contract_addr: pool_address.into(),
         msg: to_binary(&WasmswapQueryMsg::Info {})?,
     }))?;
+    Ok(pool_info_response)
+}
 
-    if denom != pool_info_response.token1_denom && denom != pool_info_response.token2_denom {
-        return Err(ContractError::PoolAndTokenMismatch{});
-    }
+/// Returns the reserves of `denom` and of the other token held by the pool
+pub fn get_pool_reserves(
+    querier: QuerierWrapper,
+    pool_address: Addr,
+    denom: Denom,
+) -> Result<(Uint128, Uint128), ContractError> {
+    let pool_info_response = get_pool_info(querier, pool_address)?;
 
     if denom == pool_info_response.token1_denom {
-        return Ok(true);
+        return Ok((pool_info_response.token1_reserve, pool_info_response.token2_reserve));
     }
-
     if denom == pool_info_response.token2_denom {
-        return Ok(false);
+        return Ok((pool_info_response.token2_reserve, pool_info_response.token1_reserve));
+    }
+    Err(ContractError::PoolAndTokenMismatch{})
+}
+
+/// Constant product output for `input_amount`, after the 0.3% wasmswap fee
+pub fn get_input_price(
+    input_amount: Uint128,
+    input_reserve: Uint128,
+    output_reserve: Uint128,
+) -> Uint128 {
+    if input_reserve.is_zero() || output_reserve.is_zero() {
+        return Uint128::zero();
+    }
+
+    let input_amount_with_fee = Uint256::from(input_amount) * Uint256::from(997u128);
+    let numerator = input_amount_with_fee * Uint256::from(output_reserve);
+    let denominator = Uint256::from(input_reserve) * Uint256::from(1000u128) + input_amount_with_fee;
+
+    Uint128::try_from(numerator / denominator).unwrap_or(Uint128::MAX)
+}
+
+/// Input needed for exactly `output_amount` out of a constant product pool with the 0.3% wasmswap fee, rounded up
+pub fn get_output_price(
+    output_amount: Uint128,
+    input_reserve: Uint128,
+    output_reserve: Uint128,
+) -> Result<Uint128, ContractError> {
+    if output_amount.is_zero() || output_amount >= output_reserve {
+        return Err(ContractError::InsufficientOutputAmount{});
     }
-    return Err(ContractError::PoolAndTokenMismatch{});
+
+    let numerator = Uint256::from(input_reserve) * Uint256::from(output_amount) * Uint256::from(1000u128);
+    let denominator = Uint256::from(output_reserve - output_amount) * Uint256::from(997u128);
+
+    Uint128::try_from(numerator / denominator + Uint256::from(1u128))
+        .map_err(|_| ContractError::InsufficientOutputAmount{})
 }
 
 pub fn get_amount_of_denom(
@@ -83,28 +127,34 @@ pub fn get_amount_of_denom(
     }
 }
 
-pub fn get_swap_amount_and_denom_and_message(
+/// Wasmswap pool denoms in the shape the registry caches them, for pools swapped through outside the registry
+pub fn get_wasmswap_pool(
+    querier: QuerierWrapper,
+    pool_address: Addr,
+) -> Result<PoolInfo, ContractError> {
+    let pool_info_response = get_pool_info(querier, pool_address)?;
+    Ok(PoolInfo {
+        token1_denom: pool_info_response.token1_denom,
+        token2_denom: pool_info_response.token2_denom,
+        enabled: true,
+        venue: Venue::Wasmswap,
+    })
+}
+
+/// Quotes how much of the other pool token `amount` of `denom` buys, using the pool's own price queries
+pub fn get_swap_amount_and_denom(
     querier: QuerierWrapper,
     pool_address: Addr,
+    pool: &PoolInfo,
     denom: Denom,
     amount: Uint128,
-    amount_out_min: Uint128,
-    recipient: Option<Addr>
-) -> Result<(Uint128, Denom, Vec<CosmosMsg>), ContractError> {
+) -> Result<(Uint128, Denom, TokenSelect), ContractError> {
 
-    let pool_info_response: WasmswapInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-        contract_addr: pool_address.clone().into(),
-        msg: to_binary(&WasmswapQueryMsg::Info {})?,
-    }))?;
-
-    if denom != pool_info_response.token1_denom && denom != pool_info_response.token2_denom {
+    if denom != pool.token1_denom && denom != pool.token2_denom {
         return Err(ContractError::PoolAndTokenMismatch{});
     }
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-    let swap_amount;
-    let other_denom: Denom;
-    if denom == pool_info_response.token1_denom {
+    if denom == pool.token1_denom {
         let token2_price_response: Token1ForToken2PriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
             contract_addr: pool_address.clone().into(),
             msg: to_binary(&WasmswapQueryMsg::Token1ForToken2Price {
@@ -112,16 +162,7 @@ pub fn get_swap_amount_and_denom_and_message(
             })?,
         }))?;
 
-        other_denom = pool_info_response.token2_denom;
-        swap_amount = token2_price_response.token2_amount;
-
-        if swap_amount < amount_out_min {
-            return Err(ContractError::InsufficientOutputAmount{});
-        }
-        let messages_swap = swap_token_messages(denom, TokenSelect::Token1, amount, swap_amount, pool_address.clone(), recipient)?;
-        for i in 0..messages_swap.len() {
-            messages.push(messages_swap[i].clone());
-        }
+        Ok((token2_price_response.token2_amount, pool.token2_denom.clone(), TokenSelect::Token1))
     } else {
         let token1_price_response: Token2ForToken1PriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
             contract_addr: pool_address.clone().into(),
@@ -130,14 +171,26 @@ pub fn get_swap_amount_and_denom_and_message(
             })?,
         }))?;
 
-        other_denom = pool_info_response.token1_denom;
-        swap_amount = token1_price_response.token1_amount;
+        Ok((token1_price_response.token1_amount, pool.token1_denom.clone(), TokenSelect::Token2))
+    }
+}
+
+pub fn get_swap_amount_and_denom_and_message(
+    querier: QuerierWrapper,
+    pool_address: Addr,
+    pool: &PoolInfo,
+    denom: Denom,
+    amount: Uint128,
+    amount_out_min: Uint128,
+    recipient: Option<Addr>
+) -> Result<(Uint128, Denom, Vec<CosmosMsg>), ContractError> {
 
-        let messages_swap = swap_token_messages(denom, TokenSelect::Token2, amount, swap_amount, pool_address.clone(), None)?;
-        for i in 0..messages_swap.len() {
-            messages.push(messages_swap[i].clone());
-        }
+    let (swap_amount, other_denom, input_token) = get_swap_amount_and_denom(querier, pool_address.clone(), pool, denom.clone(), amount)?;
+
+    if swap_amount < amount_out_min {
+        return Err(ContractError::InsufficientOutputAmount{});
     }
+    let messages = swap_token_messages(denom, input_token, amount, swap_amount, pool_address, recipient)?;
 
     Ok((swap_amount, other_denom, messages))
 }
@@ -242,6 +295,47 @@ pub fn transfer_token_message(
 }
 
 
+/// Returns the address allowed to mint the CW20, if it is mintable
+pub fn get_cw20_minter(
+    querier: QuerierWrapper,
+    token: Addr,
+) -> Result<Option<String>, StdError> {
+    let minter_response: Option<MinterResponse> = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: token.into(),
+        msg: to_binary(&Cw20QueryMsg::Minter {})?,
+    }))?;
+    Ok(minter_response.map(|minter| minter.minter))
+}
+
+/// The part of the cw721 query interface needed to check ownership
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw721QueryMsg {
+    Tokens {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Cw721TokensResponse {
+    pub tokens: Vec<String>,
+}
+
+/// Whether owner holds at least one NFT of the cw721 collection
+pub fn owns_nft(
+    querier: QuerierWrapper,
+    collection: Addr,
+    owner: Addr,
+) -> Result<bool, StdError> {
+    let tokens_response: Cw721TokensResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: collection.into(),
+        msg: to_binary(&Cw721QueryMsg::Tokens { owner: owner.into(), start_after: None, limit: Some(1) })?,
+    }))?;
+    Ok(!tokens_response.tokens.is_empty())
+}
+
 pub fn get_token_amount(
     querier: QuerierWrapper,
     denom: Denom,
@@ -276,3 +370,159 @@ pub fn get_tokens_amounts(
     }))?;
     return Ok(native_response.amount);        
 }
+
+/// The message hash a user signs for a buy intent. The contract address keeps an intent
+/// from being replayed against another deployment.
+pub fn intent_hash(
+    contract_addr: &Addr,
+    intent: &BuyIntent
+) -> Result<Vec<u8>, StdError> {
+    let mut hasher = Sha256::new();
+    hasher.update(contract_addr.as_bytes());
+    hasher.update(to_vec(intent)?);
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Token2 wasmswap asks for alongside `token1_amount`, rounded up the way the pool does
+pub fn get_token2_amount_required(
+    token1_amount: Uint128,
+    token1_reserve: Uint128,
+    token2_reserve: Uint128,
+) -> Uint128 {
+    token1_amount.multiply_ratio(token2_reserve, token1_reserve) + Uint128::from(1u128)
+}
+
+/// Builds a wasmswap AddLiquidity of exactly `token1_amount` and `token2_amount`, approving CW20 sides first
+pub fn add_liquidity_messages(
+    pool_address: Addr,
+    token1_denom: Denom,
+    token2_denom: Denom,
+    token1_amount: Uint128,
+    token2_amount: Uint128,
+    min_liquidity: Uint128,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut funds: Vec<Coin> = vec![];
+
+    for (denom, amount) in [(token1_denom, token1_amount), (token2_denom, token2_amount)] {
+        match denom {
+            Denom::Native(native_str) => funds.push(Coin { denom: native_str, amount }),
+            Denom::Cw20(cw20_address) => messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: cw20_address.into(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::IncreaseAllowance {
+                    spender: pool_address.clone().into(),
+                    amount,
+                    expires: None
+                })?,
+            })),
+        }
+    }
+
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: pool_address.into(),
+        funds,
+        msg: to_binary(&WasmswapExecuteMsg::AddLiquidity {
+            token1_amount,
+            min_liquidity,
+            max_token2: token2_amount,
+            expiration: None
+        })?,
+    }));
+    Ok(messages)
+}
+
+/// Builds a wasmswap RemoveLiquidity of `amount` LP tokens, approving the pool to burn them first
+pub fn remove_liquidity_messages(
+    pool_address: Addr,
+    lp_token: Addr,
+    amount: Uint128,
+    min_token1: Uint128,
+    min_token2: Uint128,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    Ok(vec![
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: lp_token.into(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::IncreaseAllowance {
+                spender: pool_address.clone().into(),
+                amount,
+                expires: None
+            })?,
+        }),
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: pool_address.into(),
+            funds: vec![],
+            msg: to_binary(&WasmswapExecuteMsg::RemoveLiquidity {
+                amount,
+                min_token1,
+                min_token2,
+                expiration: None
+            })?,
+        }),
+    ])
+}
+
+/// The ibc-hooks memo asking for the IbcLifecycleComplete sudo call once the transfer is acked or times out
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct IbcCallbackMemo {
+    ibc_callback: String,
+}
+
+/// Builds an ICS20 MsgTransfer from the contract carrying the ibc-hooks callback memo. IbcMsg::Transfer
+/// has no memo, so the message is protobuf encoded here and sent as a stargate message.
+pub fn ibc_transfer_message(
+    contract_addr: &Addr,
+    channel: &str,
+    receiver: &str,
+    token: &Coin,
+    timeout_nanos: u64,
+) -> Result<CosmosMsg, ContractError> {
+    let memo = to_vec(&IbcCallbackMemo { ibc_callback: contract_addr.to_string() })?;
+
+    let mut coin = vec![];
+    encode_bytes_field(&mut coin, 1, token.denom.as_bytes());
+    encode_bytes_field(&mut coin, 2, token.amount.to_string().as_bytes());
+
+    let mut value = vec![];
+    encode_bytes_field(&mut value, 1, b"transfer");
+    encode_bytes_field(&mut value, 2, channel.as_bytes());
+    encode_bytes_field(&mut value, 3, &coin);
+    encode_bytes_field(&mut value, 4, contract_addr.as_bytes());
+    encode_bytes_field(&mut value, 5, receiver.as_bytes());
+    // field 6, the timeout height, is left at zero so only the timestamp applies
+    encode_uint64_field(&mut value, 7, timeout_nanos);
+    encode_bytes_field(&mut value, 8, &memo);
+
+    Ok(CosmosMsg::Stargate {
+        type_url: String::from("/ibc.applications.transfer.v1.MsgTransfer"),
+        value: Binary::from(value),
+    })
+}
+
+fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Length delimited field, skipped when empty as proto3 does
+fn encode_bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    encode_varint(buf, field << 3 | 2);
+    encode_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Varint field, skipped when zero as proto3 does
+fn encode_uint64_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    if value == 0 {
+        return;
+    }
+    encode_varint(buf, field << 3);
+    encode_varint(buf, value);
+}
@@ -0,0 +1,279 @@
+use cosmwasm_std::{
+    to_binary, Addr, Coin, CosmosMsg, Decimal, QuerierWrapper, QueryRequest, Uint128, WasmMsg, WasmQuery,
+};
+use cw20::{Cw20ExecuteMsg, Denom};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::ContractError;
+use crate::state::PoolInfo;
+use crate::util;
+
+/// The kind of contract behind a registered pool
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Venue {
+    #[default]
+    Wasmswap,
+    /// Astroport or TerraSwap style pair contract
+    Astroport,
+}
+
+impl fmt::Display for Venue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Venue::Wasmswap => write!(f, "wasmswap"),
+            Venue::Astroport => write!(f, "astroport"),
+        }
+    }
+}
+
+/// A pool as the venues trade against it: its address and the denoms cached when it was registered
+#[derive(Clone, Copy)]
+pub struct PoolRef<'a> {
+    pub address: &'a Addr,
+    pub info: &'a PoolInfo,
+}
+
+pub trait SwapVenue {
+    /// Returns the two denoms traded by the pool
+    fn pool_denoms(&self, querier: QuerierWrapper, pool_address: Addr) -> Result<(Denom, Denom), ContractError>;
+
+    /// Returns the pool reserves of `denom` and of the other token
+    fn pool_reserves(&self, querier: QuerierWrapper, pool_address: Addr, denom: Denom) -> Result<(Uint128, Uint128), ContractError>;
+
+    /// Quotes how much of the other token `amount` of `denom` buys
+    fn quote(&self, querier: QuerierWrapper, pool: PoolRef, denom: Denom, amount: Uint128) -> Result<(Uint128, Denom), ContractError>;
+
+    /// Quotes the swap, checks it against `amount_out_min` and builds the messages executing it
+    fn swap(
+        &self,
+        querier: QuerierWrapper,
+        pool: PoolRef,
+        denom: Denom,
+        amount: Uint128,
+        amount_out_min: Uint128,
+        recipient: Option<Addr>
+    ) -> Result<(Uint128, Denom, Vec<CosmosMsg>), ContractError>;
+}
+
+pub fn get_venue(venue: &Venue) -> &'static dyn SwapVenue {
+    match venue {
+        Venue::Wasmswap => &WasmswapVenue,
+        Venue::Astroport => &AstroportVenue,
+    }
+}
+
+pub struct WasmswapVenue;
+
+impl SwapVenue for WasmswapVenue {
+    fn pool_denoms(&self, querier: QuerierWrapper, pool_address: Addr) -> Result<(Denom, Denom), ContractError> {
+        let pool_info_response = util::get_pool_info(querier, pool_address)?;
+        Ok((pool_info_response.token1_denom, pool_info_response.token2_denom))
+    }
+
+    fn pool_reserves(&self, querier: QuerierWrapper, pool_address: Addr, denom: Denom) -> Result<(Uint128, Uint128), ContractError> {
+        util::get_pool_reserves(querier, pool_address, denom)
+    }
+
+    fn quote(&self, querier: QuerierWrapper, pool: PoolRef, denom: Denom, amount: Uint128) -> Result<(Uint128, Denom), ContractError> {
+        let (swap_amount, other_denom, _input_token) = util::get_swap_amount_and_denom(querier, pool.address.clone(), pool.info, denom, amount)?;
+        Ok((swap_amount, other_denom))
+    }
+
+    fn swap(
+        &self,
+        querier: QuerierWrapper,
+        pool: PoolRef,
+        denom: Denom,
+        amount: Uint128,
+        amount_out_min: Uint128,
+        recipient: Option<Addr>
+    ) -> Result<(Uint128, Denom, Vec<CosmosMsg>), ContractError> {
+        util::get_swap_amount_and_denom_and_message(querier, pool.address.clone(), pool.info, denom, amount, amount_out_min, recipient)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfo {
+    Token { contract_addr: String },
+    NativeToken { denom: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairQueryMsg {
+    Pair {},
+    Pool {},
+    Simulation { offer_asset: Asset },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairExecuteMsg {
+    Swap {
+        offer_asset: Asset,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<String>,
+    },
+}
+
+/// Sent along with the offered tokens when the offer is a CW20
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairCw20HookMsg {
+    Swap {
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PairInfoResponse {
+    pub asset_infos: [AssetInfo; 2],
+    pub contract_addr: Addr,
+    pub liquidity_token: Addr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PairPoolResponse {
+    pub assets: [Asset; 2],
+    pub total_share: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulationResponse {
+    pub return_amount: Uint128,
+    pub spread_amount: Uint128,
+    pub commission_amount: Uint128,
+}
+
+impl From<AssetInfo> for Denom {
+    fn from(info: AssetInfo) -> Self {
+        match info {
+            AssetInfo::Token { contract_addr } => Denom::Cw20(Addr::unchecked(contract_addr)),
+            AssetInfo::NativeToken { denom } => Denom::Native(denom),
+        }
+    }
+}
+
+impl From<Denom> for AssetInfo {
+    fn from(denom: Denom) -> Self {
+        match denom {
+            Denom::Cw20(contract_addr) => AssetInfo::Token { contract_addr: contract_addr.into() },
+            Denom::Native(denom) => AssetInfo::NativeToken { denom },
+        }
+    }
+}
+
+pub struct AstroportVenue;
+
+impl SwapVenue for AstroportVenue {
+    fn pool_denoms(&self, querier: QuerierWrapper, pool_address: Addr) -> Result<(Denom, Denom), ContractError> {
+        let pair_info_response: PairInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: pool_address.into(),
+            msg: to_binary(&PairQueryMsg::Pair {})?,
+        }))?;
+
+        let [asset_info1, asset_info2] = pair_info_response.asset_infos;
+        Ok((asset_info1.into(), asset_info2.into()))
+    }
+
+    fn pool_reserves(&self, querier: QuerierWrapper, pool_address: Addr, denom: Denom) -> Result<(Uint128, Uint128), ContractError> {
+        let pool_response: PairPoolResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: pool_address.into(),
+            msg: to_binary(&PairQueryMsg::Pool {})?,
+        }))?;
+
+        let [asset1, asset2] = pool_response.assets;
+        if Denom::from(asset1.info.clone()) == denom {
+            return Ok((asset1.amount, asset2.amount));
+        }
+        if Denom::from(asset2.info.clone()) == denom {
+            return Ok((asset2.amount, asset1.amount));
+        }
+        Err(ContractError::PoolAndTokenMismatch{})
+    }
+
+    fn quote(&self, querier: QuerierWrapper, pool: PoolRef, denom: Denom, amount: Uint128) -> Result<(Uint128, Denom), ContractError> {
+        let other_denom = if denom == pool.info.token1_denom {
+            pool.info.token2_denom.clone()
+        } else if denom == pool.info.token2_denom {
+            pool.info.token1_denom.clone()
+        } else {
+            return Err(ContractError::PoolAndTokenMismatch{});
+        };
+
+        let simulation_response: SimulationResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: pool.address.into(),
+            msg: to_binary(&PairQueryMsg::Simulation {
+                offer_asset: Asset { info: denom.into(), amount }
+            })?,
+        }))?;
+
+        Ok((simulation_response.return_amount, other_denom))
+    }
+
+    fn swap(
+        &self,
+        querier: QuerierWrapper,
+        pool: PoolRef,
+        denom: Denom,
+        amount: Uint128,
+        amount_out_min: Uint128,
+        recipient: Option<Addr>
+    ) -> Result<(Uint128, Denom, Vec<CosmosMsg>), ContractError> {
+        let (swap_amount, other_denom) = self.quote(querier, pool, denom.clone(), amount)?;
+
+        if swap_amount < amount_out_min || swap_amount.is_zero() {
+            return Err(ContractError::InsufficientOutputAmount{});
+        }
+
+        // pair contracts have no min output, so the quote is passed as the belief price. The swap runs in
+        // the same block as the quote, the spread only has to absorb the rounding of the price.
+        let belief_price = Some(Decimal::from_ratio(amount, swap_amount));
+        let max_spread = Some(Decimal::permille(1));
+        let to = recipient.map(String::from);
+
+        let message = match denom.clone() {
+            Denom::Native(native_str) => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: pool.address.into(),
+                funds: vec![Coin {
+                    denom: native_str,
+                    amount
+                }],
+                msg: to_binary(&PairExecuteMsg::Swap {
+                    offer_asset: Asset { info: denom.into(), amount },
+                    belief_price,
+                    max_spread,
+                    to,
+                })?,
+            }),
+            Denom::Cw20(cw20_address) => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: cw20_address.into(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: pool.address.into(),
+                    amount,
+                    msg: to_binary(&PairCw20HookMsg::Swap {
+                        belief_price,
+                        max_spread,
+                        to,
+                    })?,
+                })?,
+            }),
+        };
+
+        Ok((swap_amount, other_denom, vec![message]))
+    }
+}
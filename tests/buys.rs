@@ -0,0 +1,419 @@
+mod common;
+
+use cosmwasm_std::{coins, to_binary, BankMsg, Binary, Coin, CosmosMsg, Decimal, Event, Uint128, Uint64, WasmMsg};
+use cw20::Cw20ExecuteMsg;
+
+use buytoken::error::ContractError;
+use buytoken::events::{BUY_EVENT, SWEEP_EVENT};
+use buytoken::msg::{BuyTokenResponse, ExecuteMsg, PoolsResponse, PurchaseHookMsg, QueryMsg, SimulateBuyExactOutResponse, SimulateBuyResponse, UserBalancesResponse};
+use buytoken::util::{get_input_price, get_output_price};
+use buytoken::venue::{Asset, AssetInfo, PairExecuteMsg};
+
+use common::*;
+
+const POOL2: &str = "pool2";
+const PAIR: &str = "pair";
+
+fn total(res: &cosmwasm_std::Response, key: &str) -> u128 {
+    events(res, BUY_EVENT).map(|event| attribute(event, key).parse::<u128>().unwrap()).sum()
+}
+
+#[test]
+fn registry_only_takes_enabled_ujuno_pools() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite.add_pool("atomtoken", atom(), token(), 1_000, 1_000);
+
+    let err = suite.execute(OWNER, &[], ExecuteMsg::RegisterPool { pool_address: String::from("atomtoken"), venue: None }).unwrap_err();
+    assert!(matches!(err, ContractError::PoolAndTokenMismatch {}));
+    let err = suite.execute(BOT, &[], ExecuteMsg::RegisterPool { pool_address: String::from(POOL2), venue: None }).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+
+    suite.add_pool(POOL2, ujuno(), token(), 1_000_000_000, 1_000_000_000);
+    let mut buy = buy_msg(1_000_000, 0);
+    if let ExecuteMsg::BuyToken { pool_address, .. } = &mut buy {
+        *pool_address = String::from(POOL2);
+    }
+    let err = suite.execute(BOT, &[], buy.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::PoolNotRegistered {}));
+
+    suite.register_pool(POOL2);
+    suite.execute(OWNER, &[], ExecuteMsg::SetPoolEnabled { pool_address: String::from(POOL2), enabled: false }).unwrap();
+    let err = suite.execute(BOT, &[], buy).unwrap_err();
+    assert!(matches!(err, ContractError::PoolDisabled {}));
+
+    let pools: PoolsResponse = suite.query(QueryMsg::ListPools { start_after: None, limit: None });
+    let listed: Vec<(String, bool)> = pools.pools.into_iter().map(|pool| (pool.pool_address.into_string(), pool.enabled)).collect();
+    assert_eq!(listed, vec![(String::from(POOL), true), (String::from(POOL2), false)]);
+}
+
+#[test]
+fn best_pool_picks_the_better_quote() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite.add_pool(POOL2, token(), ujuno(), 2_000_000_000, 1_000_000_000);
+    suite.register_pool(POOL2);
+
+    let res = suite.execute(BOT, &[], ExecuteMsg::BuyTokenBestPool {
+        juno_amount: Uint128::from(1_000_000u128),
+        token_amount_per_native: Uint128::zero(),
+        slippage_bips: Uint128::zero(),
+        recipient: String::from(RECIPIENT),
+        target_denom: token(),
+        platform_fee_bips: None,
+        gas_estimate: Uint128::zero(),
+        deadline: Uint64::new(u64::MAX),
+    }).unwrap();
+
+    let quoted = get_input_price(Uint128::from(990_000u128), Uint128::from(1_000_000_000u128), Uint128::from(2_000_000_000u128));
+    assert_eq!(response_attribute(&res, "pool"), POOL2);
+    assert_eq!(response_attribute(&res, "quoted_amount"), quoted.to_string());
+    assert_eq!(attribute(event(&res, BUY_EVENT), "pool"), POOL2);
+}
+
+fn split_msg(juno_amount: u128, max_pools: u32) -> ExecuteMsg {
+    ExecuteMsg::BuyTokenSplit {
+        juno_amount: Uint128::from(juno_amount),
+        token_amount_per_native: Uint128::zero(),
+        slippage_bips: Uint128::zero(),
+        recipient: String::from(RECIPIENT),
+        target_denom: token(),
+        max_pools,
+        platform_fee_bips: None,
+        gas_estimate: Uint128::from(10_001u128),
+        deadline: Uint64::new(u64::MAX),
+    }
+}
+
+#[test]
+fn split_shares_the_fee_and_gas_across_legs() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 100_000_000);
+    // deep enough that the second pool only takes a minority of the input
+    suite.set_pool_reserves(POOL, 100_000_000, 100_000_000);
+    suite.add_pool(POOL2, ujuno(), token(), 50_000_000, 50_000_000);
+    suite.register_pool(POOL2);
+
+    let err = suite.execute(BOT, &[], split_msg(10_000_003, 0)).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidSplit {}));
+
+    let res = suite.execute(BOT, &[], split_msg(10_000_003, 2)).unwrap();
+    let legs: Vec<&Event> = events(&res, BUY_EVENT).collect();
+    assert_eq!(legs.len(), 2);
+    assert_ne!(attribute(legs[0], "pool"), attribute(legs[1], "pool"));
+    assert_ne!(attribute(legs[0], "trade_id"), attribute(legs[1], "trade_id"));
+
+    // the legs add back up to the buy: 1% fee, 10001 gas and the rest swapped
+    assert_eq!(total(&res, "input_amount"), 10_000_003);
+    assert_eq!(total(&res, "platform_fee"), 100_000);
+    assert_eq!(total(&res, "gas_reimbursement"), 10_001);
+    let swapped: u128 = res.attributes.iter()
+        .filter(|attr| attr.key == "pool_input")
+        .map(|attr| attr.value.parse::<u128>().unwrap())
+        .sum();
+    assert_eq!(swapped, 10_000_003 - 100_000 - 10_001);
+    assert_eq!(res.messages.len(), 2);
+
+    let status: buytoken::msg::StatusResponse = suite.query(QueryMsg::Status {});
+    assert_eq!(status.pending_platform_fee, Uint128::from(100_000u128));
+    assert_eq!(status.pending_gas_reimbursement, Uint128::from(10_001u128));
+    assert_eq!(status.trade_count, 2);
+
+    // a single pool takes everything
+    let res = suite.execute(BOT, &[], split_msg(10_000_003, 1)).unwrap();
+    assert_eq!(events(&res, BUY_EVENT).count(), 1);
+    assert_eq!(attribute(event(&res, BUY_EVENT), "pool"), POOL);
+    assert_eq!(total(&res, "input_amount"), 10_000_003);
+}
+
+#[test]
+fn astroport_pairs_are_quoted_and_swapped_through_the_pair_api() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite.add_pair(PAIR, ujuno(), token(), 1_000_000_000, 2_000_000_000);
+
+    // a pair answers none of the wasmswap queries
+    let err = suite.execute(OWNER, &[], ExecuteMsg::RegisterPool { pool_address: String::from(PAIR), venue: None });
+    assert!(err.is_err());
+    suite.register_pair(PAIR);
+
+    let mut buy = buy_msg(1_000_000, 0);
+    if let ExecuteMsg::BuyToken { pool_address, .. } = &mut buy {
+        *pool_address = String::from(PAIR);
+    }
+    let res = suite.execute(BOT, &[], buy).unwrap();
+
+    let quoted = get_input_price(Uint128::from(990_000u128), Uint128::from(1_000_000_000u128), Uint128::from(2_000_000_000u128));
+    assert_eq!(attribute(event(&res, BUY_EVENT), "pool"), PAIR);
+    assert_eq!(attribute(event(&res, BUY_EVENT), "quoted_output"), quoted.to_string());
+    assert_eq!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from(PAIR),
+        funds: coins(990_000, "ujuno"),
+        msg: to_binary(&PairExecuteMsg::Swap {
+            offer_asset: Asset { info: AssetInfo::NativeToken { denom: String::from("ujuno") }, amount: Uint128::from(990_000u128) },
+            belief_price: Some(Decimal::from_ratio(990_000u128, quoted)),
+            max_spread: Some(Decimal::permille(1)),
+            to: Some(String::from(RECIPIENT)),
+        }).unwrap(),
+    }));
+}
+
+#[test]
+fn slippage_floor_rejects_a_thin_quote() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    let mut buy = buy_msg(1_000_000, 0);
+    if let ExecuteMsg::BuyToken { token_amount_per_native, slippage_bips, .. } = &mut buy {
+        // 2 tokens per ujuno against a 1:1 pool
+        *token_amount_per_native = Uint128::from(2_000_000u128);
+        *slippage_bips = Uint128::from(100u128);
+    }
+
+    let err = suite.execute(BOT, &[], buy).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientOutputAmount {}));
+}
+
+fn simulate_msg(token_amount_per_native: u128, bot: Option<&str>) -> QueryMsg {
+    QueryMsg::SimulateBuy {
+        juno_amount: Uint128::from(1_000_000u128),
+        token_amount_per_native: Uint128::from(token_amount_per_native),
+        slippage_bips: Uint128::from(100u128),
+        recipient: String::from(RECIPIENT),
+        pool: String::from(POOL),
+        platform_fee_bips: None,
+        gas_estimate: Uint128::from(5_000u128),
+        deadline: Uint64::new(u64::MAX),
+        bot: bot.map(String::from),
+        intent: None,
+    }
+}
+
+#[test]
+fn simulate_buy_previews_the_buy() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+
+    // 0.9 tokens per ujuno, less 1% slippage
+    let simulated: SimulateBuyResponse = suite.query(simulate_msg(900_000, Some(BOT)));
+    let quoted = get_input_price(Uint128::from(985_000u128), Uint128::from(1_000_000_000u128), Uint128::from(1_000_000_000u128));
+    assert_eq!(simulated, SimulateBuyResponse {
+        expected_output: quoted,
+        amount_out_min: Uint128::from(985_000u128 * 900_000 * 9_900 / 10_000_000_000),
+        output_denom: Some(token()),
+        platform_fee: Uint128::from(10_000u128),
+        gas_reimbursement: Uint128::from(5_000u128),
+        swap_amount: Uint128::from(985_000u128),
+        error: None,
+    });
+
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 5_000)).unwrap();
+    assert_eq!(attribute(event(&res, BUY_EVENT), "quoted_output"), simulated.expected_output.to_string());
+}
+
+#[test]
+fn simulate_buy_reports_why_the_buy_would_fail() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+
+    // 2 tokens per ujuno against a 1:1 pool
+    let simulated: SimulateBuyResponse = suite.query(simulate_msg(2_000_000, None));
+    assert_eq!(simulated.error, Some(String::from("Insufficient Output Amount")));
+    assert_eq!(simulated.expected_output, Uint128::zero());
+
+    suite.execute(OWNER, &[], ExecuteMsg::SetBotRole { new_bot: String::from(BOT), enabled: false }).unwrap();
+    let simulated: SimulateBuyResponse = suite.query(simulate_msg(0, Some(BOT)));
+    assert_eq!(simulated.error, Some(String::from("Unauthorized Role")));
+}
+
+fn exact_out_msg(desired_output: u128, gas_estimate: u128) -> ExecuteMsg {
+    ExecuteMsg::BuyTokenExactOut {
+        desired_output: Uint128::from(desired_output),
+        recipient: String::from(RECIPIENT),
+        pool_address: String::from(POOL),
+        platform_fee_bips: None,
+        gas_estimate: Uint128::from(gas_estimate),
+        deadline: Uint64::new(u64::MAX),
+    }
+}
+
+#[test]
+fn exact_out_charges_the_grossed_up_input_and_refunds_the_rest() {
+    let mut suite = Suite::new();
+    let reserve = Uint128::from(1_000_000_000u128);
+    let swap_amount = get_output_price(Uint128::from(1_000_000u128), reserve, reserve).unwrap();
+    // (swap + gas) / 99%, rounded up
+    let juno_amount = ((swap_amount.u128() + 5_000) * 10_000 + 9_899) / 9_900;
+
+    let simulated: SimulateBuyExactOutResponse = suite.query(QueryMsg::SimulateBuyExactOut {
+        desired_output: Uint128::from(1_000_000u128),
+        pool: String::from(POOL),
+        recipient: String::from(RECIPIENT),
+        platform_fee_bips: None,
+        gas_estimate: Uint128::from(5_000u128),
+    });
+    assert_eq!(simulated.juno_amount, Uint128::from(juno_amount));
+    assert_eq!(simulated.swap_amount, Uint128::from(juno_amount - juno_amount / 100 - 5_000));
+    assert!(simulated.swap_amount >= swap_amount);
+    assert_eq!(simulated.error, None);
+
+    let err = suite.execute(BOT, &coins(juno_amount - 1, "ujuno"), exact_out_msg(1_000_000, 5_000)).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientToken {}));
+    let funds = vec![Coin::new(2_000_000, "ujuno"), Coin::new(1, ATOM)];
+    let err = suite.execute(BOT, &funds, exact_out_msg(1_000_000, 5_000)).unwrap_err();
+    assert!(matches!(err, ContractError::UnsupportedDenom {}));
+
+    suite.set_native(CONTRACT, "ujuno", 2_000_000);
+    let res = suite.execute(BOT, &coins(2_000_000, "ujuno"), exact_out_msg(1_000_000, 5_000)).unwrap();
+    let buy = event(&res, BUY_EVENT);
+    assert_eq!(attribute(buy, "input_amount"), juno_amount.to_string());
+    assert_eq!(attribute(buy, "amount_out_min"), "1000000");
+    assert_eq!(attribute(buy, "gas_reimbursement"), "5000");
+    assert_eq!(attribute(buy, "platform_fee"), (juno_amount / 100).to_string());
+
+    let refund = 2_000_000 - juno_amount;
+    assert_eq!(response_attribute(&res, "refund"), refund.to_string());
+    assert_eq!(res.messages.last().unwrap().msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from(BOT),
+        amount: coins(refund, "ujuno"),
+    }));
+}
+
+#[test]
+fn buy_reports_the_output_the_recipient_received() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite.set_cw20(TOKEN, RECIPIENT, 500);
+
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 0)).unwrap();
+    let quoted = get_input_price(Uint128::from(990_000u128), Uint128::from(1_000_000_000u128), Uint128::from(1_000_000_000u128));
+    assert_eq!(attribute(event(&res, BUY_EVENT), "quoted_output"), quoted.to_string());
+    assert_eq!(attribute(event(&res, BUY_EVENT), "output_denom"), TOKEN);
+
+    // the pool delivers a little less than quoted
+    let delivered = quoted.u128() - 7;
+    suite.add_cw20(TOKEN, RECIPIENT, delivered);
+    let reply = suite.reply(&res).unwrap();
+    let output: BuyTokenResponse = data(&reply);
+    assert_eq!(output.trade_id, 1);
+    assert_eq!(output.input_amount, Uint128::from(1_000_000u128));
+    assert_eq!(output.platform_fee, Uint128::from(10_000u128));
+    assert_eq!(output.quoted_output, quoted);
+    assert_eq!(output.actual_output, Uint128::from(delivered));
+    assert_eq!(response_attribute(&reply, "actual_output"), delivered.to_string());
+}
+
+#[test]
+fn callback_buy_hands_the_output_to_the_recipient_contract() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite.set_cw20(TOKEN, CONTRACT, 100);
+
+    let order = Binary::from(b"order-7".to_vec());
+    let mut buy = buy_msg(1_000_000, 0);
+    if let ExecuteMsg::BuyToken { callback, .. } = &mut buy {
+        *callback = Some(order.clone());
+    }
+    let res = suite.execute(BOT, &[], buy).unwrap();
+
+    // the output lands in the contract, whatever it held before is not part of it
+    suite.add_cw20(TOKEN, CONTRACT, 900_000);
+    let reply = suite.reply(&res).unwrap();
+    assert_eq!(data::<BuyTokenResponse>(&reply).actual_output, Uint128::from(900_000u128));
+    assert_eq!(response_attribute(&reply, "callback"), RECIPIENT);
+    assert_eq!(reply.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from(TOKEN),
+        msg: to_binary(&Cw20ExecuteMsg::Send {
+            contract: String::from(RECIPIENT),
+            amount: Uint128::from(900_000u128),
+            msg: to_binary(&PurchaseHookMsg::ReceivePurchase {
+                buyer: String::from(BOT),
+                amount: Uint128::from(900_000u128),
+                denom: token(),
+                msg: order,
+            }).unwrap(),
+        }).unwrap(),
+        funds: vec![],
+    }));
+}
+
+fn ibc_send_msg() -> ExecuteMsg {
+    ExecuteMsg::BuyTokenAndIbcSend {
+        token_amount_per_native: Uint128::zero(),
+        slippage_bips: Uint128::zero(),
+        pool_address: String::from(ATOM_BUY_POOL),
+        platform_fee_bips: None,
+        deadline: Uint64::new(u64::MAX),
+        channel: String::from("channel-1"),
+        remote_recipient: String::from("cosmos1remote"),
+        timeout: 600,
+    }
+}
+
+fn vested_atom_msg() -> ExecuteMsg {
+    ExecuteMsg::BuyTokenVested {
+        juno_amount: Uint128::from(1_000_000u128),
+        token_amount_per_native: Uint128::zero(),
+        slippage_bips: Uint128::zero(),
+        recipient: String::from(RECIPIENT),
+        pool_address: String::from(ATOM_BUY_POOL),
+        platform_fee_bips: None,
+        gas_estimate: Uint128::zero(),
+        deadline: Uint64::new(u64::MAX),
+        cliff: 0,
+        duration: 1_000,
+    }
+}
+
+#[test]
+fn sweep_leaves_credited_and_vested_atom_alone() {
+    let mut suite = Suite::new();
+    suite.add_pool(ATOM_BUY_POOL, atom(), ujuno(), 1_000_000_000, 1_000_000_000);
+    suite.register_pool(ATOM_BUY_POOL);
+    suite.set_native(CONTRACT, "ujuno", 10_000_000);
+
+    // a failed IBC send credits the ATOM bought to the user
+    let res = suite.execute(USER, &coins(1_000_000, "ujuno"), ibc_send_msg()).unwrap();
+    suite.add_native(CONTRACT, ATOM, 700);
+    let transfer = suite.reply(&res).unwrap();
+    assert_eq!(response_attribute(&transfer, "amount"), "700");
+    suite.reply_err(&transfer, "channel closed").unwrap();
+    let balances: UserBalancesResponse = suite.query(QueryMsg::UserBalances { user: String::from(USER) });
+    assert_eq!(balances.balances[0].denom, atom());
+    assert_eq!(balances.balances[0].amount, Uint128::from(700u128));
+
+    // and a vested ATOM buy keeps its output in the contract
+    let res = suite.execute(BOT, &[], vested_atom_msg()).unwrap();
+    assert_eq!(events(&res, SWEEP_EVENT).count(), 0);
+    suite.add_native(CONTRACT, ATOM, 300);
+    let reply = suite.reply(&res).unwrap();
+    assert_eq!(data::<BuyTokenResponse>(&reply).actual_output, Uint128::from(300u128));
+
+    // none of the 1000 ATOM held is the contract's own
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 0)).unwrap();
+    assert_eq!(events(&res, SWEEP_EVENT).count(), 0);
+    suite.reply(&res).unwrap();
+
+    // only ATOM on top of it is swept
+    suite.add_native(CONTRACT, ATOM, 42);
+    let res = suite.execute(BOT, &[], ExecuteMsg::SwapAtomToJuno {}).unwrap();
+    let sweep = event(&res, SWEEP_EVENT);
+    assert_eq!(attribute(sweep, "input_amount"), "42");
+    assert_eq!(attribute(sweep, "input_denom"), ATOM);
+}
+
+#[test]
+fn vested_atom_buy_measures_its_output_past_the_sweep() {
+    let mut suite = Suite::new();
+    suite.add_pool(ATOM_BUY_POOL, atom(), ujuno(), 1_000_000_000, 1_000_000_000);
+    suite.register_pool(ATOM_BUY_POOL);
+    suite.set_native(CONTRACT, "ujuno", 10_000_000);
+    suite.set_native(CONTRACT, ATOM, 50);
+
+    // the sweep takes the 50 free ATOM out before the swap delivers 300
+    let res = suite.execute(BOT, &[], vested_atom_msg()).unwrap();
+    assert_eq!(attribute(event(&res, SWEEP_EVENT), "input_amount"), "50");
+    suite.set_native(CONTRACT, ATOM, 300);
+    let reply = suite.reply(&res).unwrap();
+    assert_eq!(data::<BuyTokenResponse>(&reply).actual_output, Uint128::from(300u128));
+    assert_eq!(response_attribute(&reply, "vesting_id"), "1");
+}
@@ -0,0 +1,346 @@
+//! A mocked chain for driving the contract through its entry points: bank balances, wasmswap pools,
+//! astroport pairs, CW20s, cw721 collections and validators, all adjustable between a call and the reply it triggers.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    from_binary, to_binary, Coin, ContractResult, Decimal, Env, Event, FullDelegation, OwnedDeps, QuerierResult,
+    Reply, ReplyOn, Response, SubMsgResponse, SubMsgResult, SystemError, SystemResult, Uint128, Uint64, Validator, WasmQuery,
+    Addr,
+};
+use cw20::{BalanceResponse, Cw20QueryMsg, Denom, MinterResponse};
+use serde::de::DeserializeOwned;
+use wasmswap::msg::{InfoResponse, QueryMsg as WasmswapQueryMsg, Token1ForToken2PriceResponse, Token2ForToken1PriceResponse};
+
+use buytoken::contract::{execute, instantiate, query, reply, sudo};
+use buytoken::error::ContractError;
+use buytoken::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, SudoMsg};
+use buytoken::util::{get_input_price, Cw721QueryMsg, Cw721TokensResponse};
+use buytoken::venue::{Asset, PairInfoResponse, PairPoolResponse, PairQueryMsg, SimulationResponse, Venue};
+
+pub const CONTRACT: &str = MOCK_CONTRACT_ADDR;
+pub const OWNER: &str = "owner";
+pub const BOT: &str = "bot";
+pub const USER: &str = "user";
+pub const RECIPIENT: &str = "recipient";
+pub const POOL: &str = "pool";
+pub const TOKEN: &str = "token";
+pub const LP_TOKEN: &str = "lptoken";
+pub const VALIDATOR: &str = "validator";
+
+pub const ATOM: &str = "ibc/C4CFF46FD6DE35CA4CF4CE031E643C8FDC9BA4B99AE598E9B0ED98FE3A2319F9";
+pub const ATOM_POOL: &str = "juno1sg6chmktuhyj4lsrxrrdflem7gsnk4ejv6zkcc4d3vcqulzp55wsf4l4gl";
+/// Pays out ATOM like ATOM_POOL, under an address short enough for the mock api
+pub const ATOM_BUY_POOL: &str = "atompool";
+
+/// 1% by default, at most 5%
+pub const DEFAULT_FEE_BIPS: u128 = 100;
+pub const MAX_FEE_BIPS: u128 = 500;
+/// 0.025 ujuno per gas up to 1M gas, so at most 25000 ujuno per buy
+pub const MAX_GAS_REIMBURSEMENT: u128 = 25_000;
+
+pub fn ujuno() -> Denom {
+    Denom::Native(String::from("ujuno"))
+}
+
+pub fn token() -> Denom {
+    Denom::Cw20(Addr::unchecked(TOKEN))
+}
+
+pub fn atom() -> Denom {
+    Denom::Native(String::from(ATOM))
+}
+
+pub struct Pool {
+    pub token1_denom: Denom,
+    pub token2_denom: Denom,
+    pub token1_reserve: Uint128,
+    pub token2_reserve: Uint128,
+    pub lp_token_supply: Uint128,
+}
+
+/// The contracts the buytoken contract queries
+#[derive(Default)]
+pub struct Market {
+    pub pools: HashMap<String, Pool>,
+    pub pairs: HashMap<String, Pool>,
+    pub cw20_balances: HashMap<(String, String), Uint128>,
+    pub minters: HashMap<String, String>,
+    pub nft_owners: HashMap<String, Vec<String>>,
+}
+
+fn ok<T: serde::Serialize>(response: &T) -> QuerierResult {
+    SystemResult::Ok(ContractResult::Ok(to_binary(response).unwrap()))
+}
+
+impl Market {
+    fn query(&self, request: &WasmQuery) -> QuerierResult {
+        let (contract_addr, msg) = match request {
+            WasmQuery::Smart { contract_addr, msg } => (contract_addr, msg),
+            _ => return SystemResult::Err(SystemError::UnsupportedRequest { kind: String::from("wasm") }),
+        };
+
+        if let Some(pool) = self.pools.get(contract_addr) {
+            return match from_binary(msg).unwrap() {
+                WasmswapQueryMsg::Info {} => ok(&InfoResponse {
+                    token1_reserve: pool.token1_reserve,
+                    token1_denom: pool.token1_denom.clone(),
+                    token2_reserve: pool.token2_reserve,
+                    token2_denom: pool.token2_denom.clone(),
+                    lp_token_supply: pool.lp_token_supply,
+                    lp_token_address: String::from(LP_TOKEN),
+                }),
+                WasmswapQueryMsg::Token1ForToken2Price { token1_amount } => ok(&Token1ForToken2PriceResponse {
+                    token2_amount: get_input_price(token1_amount, pool.token1_reserve, pool.token2_reserve),
+                }),
+                WasmswapQueryMsg::Token2ForToken1Price { token2_amount } => ok(&Token2ForToken1PriceResponse {
+                    token1_amount: get_input_price(token2_amount, pool.token2_reserve, pool.token1_reserve),
+                }),
+                WasmswapQueryMsg::Balance { .. } => SystemResult::Err(SystemError::UnsupportedRequest { kind: String::from("balance") }),
+            };
+        }
+
+        if let Some(pair) = self.pairs.get(contract_addr) {
+            let asset = |denom: &Denom, amount: Uint128| Asset { info: denom.clone().into(), amount };
+            let msg: PairQueryMsg = match from_binary(msg) {
+                Ok(msg) => msg,
+                Err(_) => return SystemResult::Err(SystemError::UnsupportedRequest { kind: String::from("pair") }),
+            };
+            return match msg {
+                PairQueryMsg::Pair {} => ok(&PairInfoResponse {
+                    asset_infos: [pair.token1_denom.clone().into(), pair.token2_denom.clone().into()],
+                    contract_addr: Addr::unchecked(contract_addr),
+                    liquidity_token: Addr::unchecked(LP_TOKEN),
+                }),
+                PairQueryMsg::Pool {} => ok(&PairPoolResponse {
+                    assets: [asset(&pair.token1_denom, pair.token1_reserve), asset(&pair.token2_denom, pair.token2_reserve)],
+                    total_share: pair.lp_token_supply,
+                }),
+                PairQueryMsg::Simulation { offer_asset } => {
+                    let (offer_reserve, ask_reserve) = if Denom::from(offer_asset.info) == pair.token1_denom {
+                        (pair.token1_reserve, pair.token2_reserve)
+                    } else {
+                        (pair.token2_reserve, pair.token1_reserve)
+                    };
+                    ok(&SimulationResponse {
+                        return_amount: get_input_price(offer_asset.amount, offer_reserve, ask_reserve),
+                        spread_amount: Uint128::zero(),
+                        commission_amount: Uint128::zero(),
+                    })
+                }
+            };
+        }
+
+        if let Some(owners) = self.nft_owners.get(contract_addr) {
+            let Cw721QueryMsg::Tokens { owner, .. } = from_binary(msg).unwrap();
+            let tokens = owners.iter().filter(|o| **o == owner).map(|_| String::from("1")).collect();
+            return ok(&Cw721TokensResponse { tokens });
+        }
+
+        match from_binary(msg).unwrap() {
+            Cw20QueryMsg::Balance { address } => ok(&BalanceResponse {
+                balance: self.cw20_balances.get(&(contract_addr.clone(), address)).copied().unwrap_or_default(),
+            }),
+            Cw20QueryMsg::Minter {} => ok(&self.minters.get(contract_addr).map(|minter| MinterResponse { minter: minter.clone(), cap: None })),
+            _ => SystemResult::Err(SystemError::NoSuchContract { addr: contract_addr.clone() }),
+        }
+    }
+}
+
+pub struct Suite {
+    pub deps: OwnedDeps<MockStorage, MockApi, MockQuerier>,
+    pub env: Env,
+    pub market: Rc<RefCell<Market>>,
+    natives: HashMap<String, Vec<Coin>>,
+}
+
+impl Suite {
+    /// An instantiated contract with BOT enabled and POOL, ujuno against TOKEN at 1:1, registered
+    pub fn new() -> Self {
+        let mut deps = mock_dependencies();
+        let market = Rc::new(RefCell::new(Market::default()));
+        let handler = market.clone();
+        deps.querier.update_wasm(move |request| handler.borrow().query(request));
+
+        let mut suite = Suite { deps, env: mock_env(), market, natives: HashMap::new() };
+        instantiate(suite.deps.as_mut(), suite.env.clone(), mock_info(OWNER, &[]), InstantiateMsg {
+            default_fee_bips: Uint128::from(DEFAULT_FEE_BIPS),
+            max_fee_bips: Uint128::from(MAX_FEE_BIPS),
+            gas_price: Decimal::permille(25),
+            max_gas: Uint128::from(1_000_000u128),
+        }).unwrap();
+
+        suite.execute(OWNER, &[], ExecuteMsg::SetBotRole { new_bot: String::from(BOT), enabled: true }).unwrap();
+        suite.add_pool(POOL, ujuno(), token(), 1_000_000_000, 1_000_000_000);
+        suite.register_pool(POOL);
+        suite.add_pool(ATOM_POOL, atom(), ujuno(), 1_000_000_000, 1_000_000_000);
+        suite
+    }
+
+    pub fn add_pool(&mut self, address: &str, token1_denom: Denom, token2_denom: Denom, token1_reserve: u128, token2_reserve: u128) {
+        self.market.borrow_mut().pools.insert(String::from(address), Pool {
+            token1_denom,
+            token2_denom,
+            token1_reserve: Uint128::from(token1_reserve),
+            token2_reserve: Uint128::from(token2_reserve),
+            lp_token_supply: Uint128::from(1_000_000u128),
+        });
+    }
+
+    /// An astroport style pair, which only answers the pair queries
+    pub fn add_pair(&mut self, address: &str, token1_denom: Denom, token2_denom: Denom, token1_reserve: u128, token2_reserve: u128) {
+        self.market.borrow_mut().pairs.insert(String::from(address), Pool {
+            token1_denom,
+            token2_denom,
+            token1_reserve: Uint128::from(token1_reserve),
+            token2_reserve: Uint128::from(token2_reserve),
+            lp_token_supply: Uint128::from(1_000_000u128),
+        });
+    }
+
+    pub fn set_pool_reserves(&mut self, address: &str, token1_reserve: u128, token2_reserve: u128) {
+        let mut market = self.market.borrow_mut();
+        let pool = market.pools.get_mut(address).expect("no such pool");
+        pool.token1_reserve = Uint128::from(token1_reserve);
+        pool.token2_reserve = Uint128::from(token2_reserve);
+    }
+
+    pub fn register_pool(&mut self, address: &str) {
+        self.execute(OWNER, &[], ExecuteMsg::RegisterPool { pool_address: String::from(address), venue: None }).unwrap();
+    }
+
+    pub fn register_pair(&mut self, address: &str) {
+        self.execute(OWNER, &[], ExecuteMsg::RegisterPool { pool_address: String::from(address), venue: Some(Venue::Astroport) }).unwrap();
+    }
+
+    pub fn execute(&mut self, sender: &str, funds: &[Coin], msg: ExecuteMsg) -> Result<Response, ContractError> {
+        execute(self.deps.as_mut(), self.env.clone(), mock_info(sender, funds), msg)
+    }
+
+    /// Runs the reply the response asked for, as if its submessage succeeded with `events`
+    pub fn reply_with(&mut self, response: &Response, events: Vec<Event>) -> Result<Response, ContractError> {
+        let id = response.messages.iter()
+            .find(|msg| msg.reply_on != ReplyOn::Never)
+            .map(|msg| msg.id)
+            .expect("no submessage replies");
+        reply(self.deps.as_mut(), self.env.clone(), Reply { id, result: SubMsgResult::Ok(SubMsgResponse { events, data: None }) })
+    }
+
+    pub fn reply(&mut self, response: &Response) -> Result<Response, ContractError> {
+        self.reply_with(response, vec![])
+    }
+
+    /// Runs the reply the response asked for, as if its submessage failed
+    pub fn reply_err(&mut self, response: &Response, err: &str) -> Result<Response, ContractError> {
+        let id = response.messages.iter()
+            .find(|msg| msg.reply_on != ReplyOn::Never)
+            .map(|msg| msg.id)
+            .expect("no submessage replies");
+        reply(self.deps.as_mut(), self.env.clone(), Reply { id, result: SubMsgResult::Err(String::from(err)) })
+    }
+
+    pub fn sudo(&mut self, msg: SudoMsg) -> Result<Response, ContractError> {
+        sudo(self.deps.as_mut(), self.env.clone(), msg)
+    }
+
+    pub fn query<T: DeserializeOwned>(&self, msg: QueryMsg) -> T {
+        from_binary(&query(self.deps.as_ref(), self.env.clone(), msg).unwrap()).unwrap()
+    }
+
+    pub fn native(&self, address: &str, denom: &str) -> Uint128 {
+        self.natives.get(address)
+            .and_then(|coins| coins.iter().find(|coin| coin.denom == denom))
+            .map_or(Uint128::zero(), |coin| coin.amount)
+    }
+
+    pub fn set_native(&mut self, address: &str, denom: &str, amount: u128) {
+        let coins = self.natives.entry(String::from(address)).or_default();
+        coins.retain(|coin| coin.denom != denom);
+        coins.push(Coin { denom: String::from(denom), amount: Uint128::from(amount) });
+        self.deps.querier.update_balance(address, coins.clone());
+    }
+
+    pub fn add_native(&mut self, address: &str, denom: &str, amount: u128) {
+        let balance = self.native(address, denom).u128();
+        self.set_native(address, denom, balance + amount);
+    }
+
+    pub fn cw20(&self, token: &str, holder: &str) -> Uint128 {
+        self.market.borrow().cw20_balances.get(&(String::from(token), String::from(holder))).copied().unwrap_or_default()
+    }
+
+    pub fn set_cw20(&self, token: &str, holder: &str, amount: u128) {
+        self.market.borrow_mut().cw20_balances.insert((String::from(token), String::from(holder)), Uint128::from(amount));
+    }
+
+    pub fn add_cw20(&self, token: &str, holder: &str, amount: u128) {
+        let balance = self.cw20(token, holder).u128();
+        self.set_cw20(token, holder, balance + amount);
+    }
+
+    /// A validator the contract delegates `amount` ujuno to, with `rewards` ujuno accrued
+    pub fn set_delegation(&mut self, validators: &[&str], delegations: &[(&str, u128, u128)]) {
+        let validators: Vec<Validator> = validators.iter().map(|address| Validator {
+            address: String::from(*address),
+            commission: Decimal::percent(5),
+            max_commission: Decimal::percent(10),
+            max_change_rate: Decimal::percent(1),
+        }).collect();
+        let delegations: Vec<FullDelegation> = delegations.iter().map(|(validator, amount, rewards)| FullDelegation {
+            delegator: Addr::unchecked(CONTRACT),
+            validator: String::from(*validator),
+            amount: Coin { denom: String::from("ujuno"), amount: Uint128::from(*amount) },
+            can_redelegate: Coin { denom: String::from("ujuno"), amount: Uint128::from(*amount) },
+            accumulated_rewards: vec![Coin { denom: String::from("ujuno"), amount: Uint128::from(*rewards) }],
+        }).collect();
+        self.deps.querier.update_staking("ujuno", &validators, &delegations);
+    }
+}
+
+/// A BuyToken of `juno_amount` from the shared balance, at no price floor and the scheduled fee
+pub fn buy_msg(juno_amount: u128, gas_estimate: u128) -> ExecuteMsg {
+    ExecuteMsg::BuyToken {
+        juno_amount: Uint128::from(juno_amount),
+        token_amount_per_native: Uint128::zero(),
+        slippage_bips: Uint128::zero(),
+        recipient: String::from(RECIPIENT),
+        pool_address: String::from(POOL),
+        platform_fee_bips: None,
+        gas_estimate: Uint128::from(gas_estimate),
+        deadline: Uint64::new(u64::MAX),
+        intent: None,
+        callback: None,
+    }
+}
+
+pub fn event<'a>(response: &'a Response, ty: &str) -> &'a Event {
+    response.events.iter()
+        .find(|event| event.ty == ty)
+        .unwrap_or_else(|| panic!("missing event {}", ty))
+}
+
+pub fn events<'a>(response: &'a Response, ty: &'a str) -> impl Iterator<Item = &'a Event> {
+    response.events.iter().filter(move |event| event.ty == ty)
+}
+
+pub fn attribute<'a>(event: &'a Event, key: &str) -> &'a str {
+    event.attributes.iter()
+        .find(|attr| attr.key == key)
+        .map(|attr| attr.value.as_str())
+        .unwrap_or_else(|| panic!("missing attribute {}", key))
+}
+
+pub fn response_attribute<'a>(response: &'a Response, key: &str) -> &'a str {
+    response.attributes.iter()
+        .find(|attr| attr.key == key)
+        .map(|attr| attr.value.as_str())
+        .unwrap_or_else(|| panic!("missing attribute {}", key))
+}
+
+pub fn data<T: DeserializeOwned>(response: &Response) -> T {
+    from_binary(response.data.as_ref().expect("no response data")).unwrap()
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use cosmwasm_std::{Addr, Uint128};
+use cw20::Denom;
+
+use buytoken::events::{
+    admin_change_event, bot_role_event, buy_event, fee_withdraw_event, sweep_event, ADMIN_CHANGE_EVENT, BOT_ROLE_EVENT,
+    BUY_EVENT, FEE_WITHDRAW_EVENT, SWEEP_EVENT,
+};
+use buytoken::msg::ExecuteMsg;
+use buytoken::state::Trade;
+
+use common::*;
+
+fn mock_trade() -> Trade {
+    Trade {
+        id: 7,
+        bot: Addr::unchecked("bot"),
+        recipient: Addr::unchecked("recipient"),
+        pool: Addr::unchecked("pool"),
+        block_height: 12345,
+        block_time: 1_600_000_000,
+        input_denom: Denom::Native(String::from("ujuno")),
+        input_amount: Uint128::from(1_000_000u128),
+        platform_fee: Uint128::from(10_000u128),
+        gas_reimbursement: Uint128::from(5_000u128),
+        quoted_output: Uint128::from(42_000u128),
+        amount_out_min: Uint128::from(40_000u128),
+        output_denom: Denom::Cw20(Addr::unchecked("token")),
+    }
+}
+
+#[test]
+fn buy_event_attributes() {
+    let event = buy_event(&mock_trade());
+
+    assert_eq!(event.ty, BUY_EVENT);
+    assert_eq!(attribute(&event, "trade_id"), "7");
+    assert_eq!(attribute(&event, "bot"), "bot");
+    assert_eq!(attribute(&event, "recipient"), "recipient");
+    assert_eq!(attribute(&event, "pool"), "pool");
+    assert_eq!(attribute(&event, "input_denom"), "ujuno");
+    assert_eq!(attribute(&event, "input_amount"), "1000000");
+    assert_eq!(attribute(&event, "output_denom"), "token");
+    assert_eq!(attribute(&event, "quoted_output"), "42000");
+    assert_eq!(attribute(&event, "amount_out_min"), "40000");
+    assert_eq!(attribute(&event, "platform_fee"), "10000");
+    assert_eq!(attribute(&event, "gas_reimbursement"), "5000");
+}
+
+#[test]
+fn sweep_event_shares_trade_keys() {
+    let trade = mock_trade();
+    let sweep = sweep_event(&trade);
+    let buy = buy_event(&trade);
+
+    assert_eq!(sweep.ty, SWEEP_EVENT);
+    for attr in sweep.attributes.iter() {
+        assert_eq!(attribute(&buy, &attr.key), attr.value);
+    }
+    assert!(sweep.attributes.iter().all(|attr| attr.key != "platform_fee"));
+}
+
+#[test]
+fn fee_withdraw_event_attributes() {
+    let event = fee_withdraw_event(
+        &Addr::unchecked("treasury"),
+        &Denom::Native(String::from("ujuno")),
+        Uint128::from(300u128),
+        Uint128::from(700u128),
+    );
+
+    assert_eq!(event.ty, FEE_WITHDRAW_EVENT);
+    assert_eq!(attribute(&event, "recipient"), "treasury");
+    assert_eq!(attribute(&event, "denom"), "ujuno");
+    assert_eq!(attribute(&event, "amount"), "300");
+    assert_eq!(attribute(&event, "pending_platform_fee"), "700");
+}
+
+#[test]
+fn bot_role_event_reports_disabling() {
+    let enabled = bot_role_event(&Addr::unchecked("bot"), true);
+    let disabled = bot_role_event(&Addr::unchecked("bot"), false);
+
+    assert_eq!(enabled.ty, BOT_ROLE_EVENT);
+    assert_eq!(attribute(&enabled, "bot"), "bot");
+    assert_eq!(attribute(&enabled, "enabled"), "true");
+    assert_eq!(attribute(&disabled, "enabled"), "false");
+}
+
+#[test]
+fn admin_change_event_attributes() {
+    let event = admin_change_event(&Addr::unchecked("old"), &Addr::unchecked("new"));
+
+    assert_eq!(event.ty, ADMIN_CHANGE_EVENT);
+    assert_eq!(attribute(&event, "previous_admin"), "old");
+    assert_eq!(attribute(&event, "new_admin"), "new");
+}
+
+#[test]
+fn execute_emits_the_action_events() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+
+    let res = suite.execute(OWNER, &[], ExecuteMsg::SetBotRole { new_bot: String::from("bot2"), enabled: false }).unwrap();
+    assert_eq!(attribute(event(&res, BOT_ROLE_EVENT), "enabled"), "false");
+
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 5_000)).unwrap();
+    let buy = event(&res, BUY_EVENT);
+    assert_eq!(attribute(buy, "trade_id"), "1");
+    assert_eq!(attribute(buy, "bot"), BOT);
+    assert_eq!(attribute(buy, "recipient"), RECIPIENT);
+    assert_eq!(attribute(buy, "pool"), POOL);
+    assert_eq!(attribute(buy, "input_amount"), "1000000");
+    assert_eq!(events(&res, SWEEP_EVENT).count(), 0);
+
+    suite.set_native(CONTRACT, "ujuno", 15_000);
+    let res = suite.execute(OWNER, &[], ExecuteMsg::WithdrawFee { to: String::from("treasury"), amount: Uint128::from(4_000u128) }).unwrap();
+    let withdrawn = event(&res, FEE_WITHDRAW_EVENT);
+    assert_eq!(attribute(withdrawn, "amount"), "4000");
+    assert_eq!(attribute(withdrawn, "pending_platform_fee"), "6000");
+
+    let res = suite.execute(OWNER, &[], ExecuteMsg::SetAdmin { new_admin: String::from("admin2") }).unwrap();
+    let change = event(&res, ADMIN_CHANGE_EVENT);
+    assert_eq!(attribute(change, "previous_admin"), OWNER);
+    assert_eq!(attribute(change, "new_admin"), "admin2");
+}
@@ -0,0 +1,248 @@
+mod common;
+
+use cosmwasm_std::{BankMsg, CosmosMsg, Uint128};
+
+use buytoken::error::ContractError;
+use buytoken::events::{BUY_EVENT, FEE_WITHDRAW_EVENT};
+use buytoken::msg::{ExecuteMsg, GasReimbursementResponse, HolderDiscountResponse, QueryMsg, RecipientFeeResponse, StatusResponse};
+use buytoken::state::{FeeTier, HolderDiscountTier};
+
+use common::*;
+
+fn status(suite: &Suite) -> StatusResponse {
+    suite.query(QueryMsg::Status {})
+}
+
+fn buy_with_fee(platform_fee_bips: Option<u128>) -> ExecuteMsg {
+    match buy_msg(1_000_000, 0) {
+        ExecuteMsg::BuyToken { juno_amount, token_amount_per_native, slippage_bips, recipient, pool_address, gas_estimate, deadline, intent, callback, .. } =>
+            ExecuteMsg::BuyToken {
+                juno_amount, token_amount_per_native, slippage_bips, recipient, pool_address, gas_estimate, deadline, intent, callback,
+                platform_fee_bips: platform_fee_bips.map(Uint128::from),
+            },
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn buy_books_scheduled_fee_and_gas() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 5_000)).unwrap();
+    let buy = event(&res, BUY_EVENT);
+    assert_eq!(attribute(buy, "input_amount"), "1000000");
+    assert_eq!(attribute(buy, "platform_fee"), "10000");
+    assert_eq!(attribute(buy, "gas_reimbursement"), "5000");
+
+    let status = status(&suite);
+    assert_eq!(status.pending_platform_fee, Uint128::from(10_000u128));
+    assert_eq!(status.pending_gas_reimbursement, Uint128::from(5_000u128));
+    assert_eq!(status.trade_count, 1);
+}
+
+#[test]
+fn bot_fee_bips_stay_within_schedule_and_max() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 10_000_000);
+
+    let err = suite.execute(BOT, &[], buy_with_fee(Some(50))).unwrap_err();
+    assert!(matches!(err, ContractError::PlatformFeeOutOfBounds {}));
+    let err = suite.execute(BOT, &[], buy_with_fee(Some(MAX_FEE_BIPS + 1))).unwrap_err();
+    assert!(matches!(err, ContractError::PlatformFeeOutOfBounds {}));
+
+    let res = suite.execute(BOT, &[], buy_with_fee(Some(300))).unwrap();
+    assert_eq!(attribute(event(&res, BUY_EVENT), "platform_fee"), "30000");
+}
+
+#[test]
+fn volume_tiers_and_overrides_set_the_schedule() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 10_000_000);
+    suite.execute(OWNER, &[], ExecuteMsg::SetFeeConfig {
+        default_bips: Uint128::from(100u128),
+        max_bips: Uint128::from(500u128),
+        tiers: vec![FeeTier { min_volume: Uint128::from(1_000_000u128), fee_bips: Uint128::from(50u128) }],
+    }).unwrap();
+
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 0)).unwrap();
+    assert_eq!(attribute(event(&res, BUY_EVENT), "platform_fee"), "10000");
+    // the first buy lifts the recipient into the tier
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 0)).unwrap();
+    assert_eq!(attribute(event(&res, BUY_EVENT), "platform_fee"), "5000");
+
+    suite.execute(OWNER, &[], ExecuteMsg::SetFeeOverride { recipient: String::from(RECIPIENT), fee_bips: Some(Uint128::from(20u128)) }).unwrap();
+    let fee: RecipientFeeResponse = suite.query(QueryMsg::RecipientFee { recipient: String::from(RECIPIENT) });
+    assert_eq!(fee.fee_bips, Uint128::from(20u128));
+    assert!(fee.overridden);
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 0)).unwrap();
+    assert_eq!(attribute(event(&res, BUY_EVENT), "platform_fee"), "2000");
+
+    let err = suite.execute(OWNER, &[], ExecuteMsg::SetFeeOverride { recipient: String::from(RECIPIENT), fee_bips: Some(Uint128::from(501u128)) }).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidFeeConfig {}));
+}
+
+#[test]
+fn invalid_fee_configs_are_rejected() {
+    let mut suite = Suite::new();
+    let tier = |min_volume: u128, fee_bips: u128| FeeTier { min_volume: Uint128::from(min_volume), fee_bips: Uint128::from(fee_bips) };
+    let configs = vec![
+        (200u128, 100u128, vec![]),
+        (100, 10_001, vec![]),
+        (100, 500, vec![tier(1_000, 600)]),
+        (100, 500, vec![tier(1_000, 50), tier(1_000, 40)]),
+    ];
+
+    for (default_bips, max_bips, tiers) in configs {
+        let err = suite.execute(OWNER, &[], ExecuteMsg::SetFeeConfig {
+            default_bips: Uint128::from(default_bips),
+            max_bips: Uint128::from(max_bips),
+            tiers,
+        }).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidFeeConfig {}));
+    }
+
+    let err = suite.execute(BOT, &[], ExecuteMsg::SetFeeConfig { default_bips: Uint128::zero(), max_bips: Uint128::zero(), tiers: vec![] }).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+}
+
+#[test]
+fn fee_config_cannot_drop_below_an_override() {
+    let mut suite = Suite::new();
+    suite.execute(OWNER, &[], ExecuteMsg::SetFeeOverride { recipient: String::from(RECIPIENT), fee_bips: Some(Uint128::from(400u128)) }).unwrap();
+
+    let err = suite.execute(OWNER, &[], ExecuteMsg::SetFeeConfig {
+        default_bips: Uint128::from(100u128),
+        max_bips: Uint128::from(300u128),
+        tiers: vec![],
+    }).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidFeeConfig {}));
+
+    suite.execute(OWNER, &[], ExecuteMsg::SetFeeOverride { recipient: String::from(RECIPIENT), fee_bips: None }).unwrap();
+    suite.execute(OWNER, &[], ExecuteMsg::SetFeeConfig {
+        default_bips: Uint128::from(100u128),
+        max_bips: Uint128::from(300u128),
+        tiers: vec![],
+    }).unwrap();
+}
+
+#[test]
+fn gas_reimbursement_is_capped_and_claimed_once() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 40_000)).unwrap();
+    assert_eq!(attribute(event(&res, BUY_EVENT), "gas_reimbursement"), MAX_GAS_REIMBURSEMENT.to_string());
+
+    let ledger: GasReimbursementResponse = suite.query(QueryMsg::GasReimbursement { bot: String::from(BOT) });
+    assert_eq!(ledger.accrued, Uint128::from(MAX_GAS_REIMBURSEMENT));
+    assert_eq!(ledger.claimable, Uint128::from(MAX_GAS_REIMBURSEMENT));
+
+    // what the swap left behind
+    suite.set_native(CONTRACT, "ujuno", 35_000);
+    let res = suite.execute(BOT, &[], ExecuteMsg::ClaimGasReimbursement {}).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from(BOT),
+        amount: cosmwasm_std::coins(MAX_GAS_REIMBURSEMENT, "ujuno"),
+    }));
+    assert_eq!(status(&suite).pending_gas_reimbursement, Uint128::zero());
+
+    let err = suite.execute(BOT, &[], ExecuteMsg::ClaimGasReimbursement {}).unwrap_err();
+    assert!(matches!(err, ContractError::NoGasReimbursement {}));
+}
+
+#[test]
+fn disabled_bots_cannot_buy_but_claim_what_they_accrued() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite.execute(BOT, &[], buy_msg(1_000_000, 5_000)).unwrap();
+    suite.execute(OWNER, &[], ExecuteMsg::SetBotRole { new_bot: String::from(BOT), enabled: false }).unwrap();
+
+    let err = suite.execute(BOT, &[], buy_msg(1_000_000, 5_000)).unwrap_err();
+    assert!(matches!(err, ContractError::UnauthorizedRole {}));
+    let err = suite.execute("stranger", &[], buy_msg(1_000_000, 5_000)).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+
+    suite.set_native(CONTRACT, "ujuno", 15_000);
+    let res = suite.execute(BOT, &[], ExecuteMsg::ClaimGasReimbursement {}).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send {
+        to_address: String::from(BOT),
+        amount: cosmwasm_std::coins(5_000, "ujuno"),
+    }));
+    let err = suite.execute("stranger", &[], ExecuteMsg::ClaimGasReimbursement {}).unwrap_err();
+    assert!(matches!(err, ContractError::NoGasReimbursement {}));
+}
+
+#[test]
+fn withdraw_fee_leaves_gas_and_deposits_untouched() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite.execute(BOT, &[], buy_msg(1_000_000, 5_000)).unwrap();
+    // the swap took 985000, leaving the 10000 fee and the 5000 owed to the bot
+    suite.set_native(CONTRACT, "ujuno", 15_000);
+
+    let withdraw = |amount: u128| ExecuteMsg::WithdrawFee { to: String::from("treasury"), amount: Uint128::from(amount) };
+    let err = suite.execute(BOT, &[], withdraw(1_000)).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+    let err = suite.execute(OWNER, &[], withdraw(10_001)).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientFeeBalance {}));
+
+    // a deposit sits on top of the shared balance, but the fee may not be paid out of it
+    suite.execute(USER, &cosmwasm_std::coins(1_000, "ujuno"), ExecuteMsg::Deposit {}).unwrap();
+    suite.set_native(CONTRACT, "ujuno", 15_000);
+    let err = suite.execute(OWNER, &[], withdraw(10_000)).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientFeeBalance {}));
+    assert_eq!(status(&suite).withdrawable_fee, Uint128::from(9_000u128));
+
+    suite.add_native(CONTRACT, "ujuno", 1_000);
+    let res = suite.execute(OWNER, &[], withdraw(10_000)).unwrap();
+    let withdrawn = event(&res, FEE_WITHDRAW_EVENT);
+    assert_eq!(attribute(withdrawn, "recipient"), "treasury");
+    assert_eq!(attribute(withdrawn, "amount"), "10000");
+    assert_eq!(attribute(withdrawn, "pending_platform_fee"), "0");
+    assert_eq!(status(&suite).pending_platform_fee, Uint128::zero());
+}
+
+#[test]
+fn holder_discount_caps_the_bot_fee() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 10_000_000);
+    suite.set_cw20("discount", RECIPIENT, 150);
+    suite.execute(OWNER, &[], ExecuteMsg::SetHolderDiscount {
+        token: Some(String::from("discount")),
+        tiers: vec![
+            HolderDiscountTier { min_balance: Uint128::from(1_000u128), discount_bips: Uint128::from(8_000u128) },
+            HolderDiscountTier { min_balance: Uint128::from(100u128), discount_bips: Uint128::from(5_000u128) },
+        ],
+        nft_collection: None,
+        nft_discount_bips: Uint128::zero(),
+    }).unwrap();
+
+    let discount: HolderDiscountResponse = suite.query(QueryMsg::HolderDiscount {});
+    let min_balances: Vec<Uint128> = discount.config.unwrap().tiers.iter().map(|tier| tier.min_balance).collect();
+    assert_eq!(min_balances, vec![Uint128::from(100u128), Uint128::from(1_000u128)]);
+
+    let fee: RecipientFeeResponse = suite.query(QueryMsg::RecipientFee { recipient: String::from(RECIPIENT) });
+    assert_eq!(fee.fee_bips, Uint128::from(50u128));
+    assert_eq!(fee.discount_bips, Uint128::from(5_000u128));
+
+    // the bot asks for more than the discounted schedule, the recipient pays the schedule
+    let res = suite.execute(BOT, &[], buy_with_fee(Some(400))).unwrap();
+    assert_eq!(attribute(event(&res, BUY_EVENT), "platform_fee"), "5000");
+}
+
+#[test]
+fn nft_holders_get_the_collection_discount() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 10_000_000);
+    suite.market.borrow_mut().nft_owners.insert(String::from("collection"), vec![String::from(RECIPIENT)]);
+    suite.execute(OWNER, &[], ExecuteMsg::SetHolderDiscount {
+        token: None,
+        tiers: vec![],
+        nft_collection: Some(String::from("collection")),
+        nft_discount_bips: Uint128::from(2_500u128),
+    }).unwrap();
+
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 0)).unwrap();
+    assert_eq!(attribute(event(&res, BUY_EVENT), "platform_fee"), "7500");
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use cosmwasm_std::{coins, Coin, CosmosMsg, Event, Response, Uint128, Uint64};
+
+use buytoken::error::ContractError;
+use buytoken::msg::{ExecuteMsg, IbcLifecycleComplete, IbcTransferResponse, QueryMsg, SudoMsg, UserBalancesResponse};
+
+use common::*;
+
+const CHANNEL: &str = "channel-1";
+
+fn ibc_send_msg(timeout: u64) -> ExecuteMsg {
+    ExecuteMsg::BuyTokenAndIbcSend {
+        token_amount_per_native: Uint128::zero(),
+        slippage_bips: Uint128::zero(),
+        pool_address: String::from(ATOM_BUY_POOL),
+        platform_fee_bips: None,
+        deadline: Uint64::new(u64::MAX),
+        channel: String::from(CHANNEL),
+        remote_recipient: String::from("cosmos1remote"),
+        timeout,
+    }
+}
+
+fn atom_suite() -> Suite {
+    let mut suite = Suite::new();
+    suite.add_pool(ATOM_BUY_POOL, atom(), ujuno(), 1_000_000_000, 1_000_000_000);
+    suite.register_pool(ATOM_BUY_POOL);
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite
+}
+
+/// Buys `output` ATOM for USER and dispatches its transfer under `sequence`
+fn dispatch(suite: &mut Suite, output: u128, sequence: u64) -> Response {
+    let res = suite.execute(USER, &coins(1_000_000, "ujuno"), ibc_send_msg(600)).unwrap();
+    suite.add_native(CONTRACT, ATOM, output);
+    let transfer = suite.reply(&res).unwrap();
+    let packet = Event::new("send_packet").add_attribute("packet_sequence", sequence.to_string());
+    suite.reply_with(&transfer, vec![packet]).unwrap()
+}
+
+fn transfer(suite: &Suite, sequence: u64) -> IbcTransferResponse {
+    suite.query(QueryMsg::IbcTransfer { channel: String::from(CHANNEL), sequence })
+}
+
+fn user_atom(suite: &Suite) -> Uint128 {
+    let balances: UserBalancesResponse = suite.query(QueryMsg::UserBalances { user: String::from(USER) });
+    balances.balances.into_iter().find(|balance| balance.denom == atom()).map_or(Uint128::zero(), |balance| balance.amount)
+}
+
+#[test]
+fn ibc_send_transfers_the_measured_output() {
+    let mut suite = atom_suite();
+
+    let res = suite.execute(USER, &coins(1_000_000, "ujuno"), ibc_send_msg(600)).unwrap();
+    suite.add_native(CONTRACT, ATOM, 700);
+    let transfer_res = suite.reply(&res).unwrap();
+    assert_eq!(response_attribute(&transfer_res, "amount"), "700");
+    assert_eq!(response_attribute(&transfer_res, "denom"), ATOM);
+    assert!(matches!(&transfer_res.messages[0].msg, CosmosMsg::Stargate { type_url, .. } if type_url == "/ibc.applications.transfer.v1.MsgTransfer"));
+
+    let packet = Event::new("send_packet").add_attribute("packet_sequence", "5");
+    let dispatched = suite.reply_with(&transfer_res, vec![packet]).unwrap();
+    assert_eq!(response_attribute(&dispatched, "sequence"), "5");
+    let tracked = transfer(&suite, 5).transfer.unwrap();
+    assert_eq!(tracked.amount, Uint128::from(700u128));
+
+    let res = suite.sudo(SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcAck {
+        channel: String::from(CHANNEL),
+        sequence: 5,
+        ack: String::from("AQ=="),
+        success: true,
+    })).unwrap();
+    assert_eq!(response_attribute(&res, "delivered"), "true");
+    assert_eq!(transfer(&suite, 5).transfer, None);
+    assert_eq!(user_atom(&suite), Uint128::zero());
+}
+
+#[test]
+fn failed_ack_and_timeout_credit_the_user() {
+    let mut suite = atom_suite();
+    dispatch(&mut suite, 700, 1);
+    dispatch(&mut suite, 300, 2);
+
+    let res = suite.sudo(SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcAck {
+        channel: String::from(CHANNEL),
+        sequence: 1,
+        ack: String::from("error"),
+        success: false,
+    })).unwrap();
+    assert_eq!(response_attribute(&res, "delivered"), "false");
+    assert_eq!(response_attribute(&res, "user"), USER);
+    assert_eq!(user_atom(&suite), Uint128::from(700u128));
+
+    suite.sudo(SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout { channel: String::from(CHANNEL), sequence: 2 })).unwrap();
+    assert_eq!(user_atom(&suite), Uint128::from(1_000u128));
+
+    // each transfer completes once
+    let err = suite.sudo(SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout { channel: String::from(CHANNEL), sequence: 2 }));
+    assert!(err.is_err());
+    assert_eq!(user_atom(&suite), Uint128::from(1_000u128));
+}
+
+#[test]
+fn rejected_transfer_credits_the_user() {
+    let mut suite = atom_suite();
+
+    let res = suite.execute(USER, &coins(1_000_000, "ujuno"), ibc_send_msg(600)).unwrap();
+    suite.add_native(CONTRACT, ATOM, 700);
+    let transfer_res = suite.reply(&res).unwrap();
+    let failed = suite.reply_err(&transfer_res, "channel closed").unwrap();
+    assert_eq!(response_attribute(&failed, "action"), "ibc_send_failed");
+    assert_eq!(response_attribute(&failed, "error"), "channel closed");
+    assert_eq!(user_atom(&suite), Uint128::from(700u128));
+}
+
+#[test]
+fn ibc_send_takes_ujuno_within_a_bounded_timeout() {
+    let mut suite = atom_suite();
+
+    let err = suite.execute(USER, &coins(1_000_000, "ujuno"), ibc_send_msg(0)).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidTimeout {}));
+    // far enough out to overflow the timestamp
+    let err = suite.execute(USER, &coins(1_000_000, "ujuno"), ibc_send_msg(u64::MAX)).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidTimeout {}));
+    let err = suite.execute(USER, &coins(1_000_000, "ujuno"), ibc_send_msg(7 * 24 * 60 * 60 + 1)).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidTimeout {}));
+    suite.execute(USER, &coins(1_000_000, "ujuno"), ibc_send_msg(7 * 24 * 60 * 60)).unwrap();
+
+    let funds = vec![Coin::new(1_000_000, "ujuno"), Coin::new(1, ATOM)];
+    let err = suite.execute(USER, &funds, ibc_send_msg(600)).unwrap_err();
+    assert!(matches!(err, ContractError::UnsupportedDenom {}));
+}
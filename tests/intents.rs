@@ -0,0 +1,204 @@
+mod common;
+
+use cosmwasm_std::testing::MOCK_CONTRACT_ADDR;
+use cosmwasm_std::{coins, to_binary, to_vec, Binary, CosmosMsg, Decimal, Uint128, Uint64, WasmMsg};
+use cw20::Cw20ExecuteMsg;
+use k256::ecdsa::signature::Signer;
+use k256::ecdsa::{Signature, SigningKey};
+
+use buytoken::error::ContractError;
+use buytoken::events::BUY_EVENT;
+use buytoken::msg::{BuyIntent, ExecuteMsg, IntentNonceResponse, LoyaltyResponse, QueryMsg, SignedBuyIntent, UserBalancesResponse};
+
+use common::*;
+
+const LOYALTY: &str = "loyalty";
+
+fn user_key() -> SigningKey {
+    SigningKey::from_bytes(&[7u8; 32]).unwrap()
+}
+
+fn intent(recipient: &str, nonce: u64) -> BuyIntent {
+    BuyIntent {
+        user: String::from(USER),
+        max_juno_amount: Uint128::from(1_000_000u128),
+        min_token_amount_per_native: Uint128::zero(),
+        max_slippage_bips: Uint128::zero(),
+        max_platform_fee_bips: Uint128::from(DEFAULT_FEE_BIPS),
+        max_gas_reimbursement: Uint128::from(5_000u128),
+        recipient: String::from(recipient),
+        pool_address: String::from(POOL),
+        nonce,
+        expiry: Uint64::new(u64::MAX),
+    }
+}
+
+/// Signs sha256(contract address || JSON of the intent), the hash `util::intent_hash` checks against
+fn sign(key: &SigningKey, intent: BuyIntent) -> SignedBuyIntent {
+    let message = [MOCK_CONTRACT_ADDR.as_bytes(), &to_vec(&intent).unwrap()].concat();
+    let signature: Signature = key.sign(&message);
+    SignedBuyIntent { intent, signature: Binary::from(signature.as_ref()) }
+}
+
+fn intent_buy(juno_amount: u128, signed: SignedBuyIntent) -> ExecuteMsg {
+    let recipient = signed.intent.recipient.clone();
+    match buy_msg(juno_amount, 5_000) {
+        ExecuteMsg::BuyToken { token_amount_per_native, slippage_bips, pool_address, platform_fee_bips, gas_estimate, deadline, callback, .. } =>
+            ExecuteMsg::BuyToken {
+                juno_amount: Uint128::from(juno_amount),
+                token_amount_per_native, slippage_bips, recipient, pool_address, platform_fee_bips, gas_estimate, deadline, callback,
+                intent: Some(Box::new(signed)),
+            },
+        _ => unreachable!(),
+    }
+}
+
+/// USER with a 1_000_000 ujuno deposit and an intent key, the only ujuno the contract holds
+fn funded_suite() -> Suite {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite.execute(USER, &coins(1_000_000, "ujuno"), ExecuteMsg::Deposit {}).unwrap();
+    let pubkey = user_key().verifying_key().to_bytes();
+    suite.execute(USER, &[], ExecuteMsg::SetIntentKey { pubkey: Binary::from(&pubkey[..]) }).unwrap();
+    suite
+}
+
+#[test]
+fn intent_buy_spends_the_deposit_once() {
+    let mut suite = funded_suite();
+
+    // the deposit is not the bots' to spend without an intent
+    let err = suite.execute(BOT, &[], buy_msg(1_000_000, 5_000)).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientToken {}));
+
+    let signed = sign(&user_key(), intent(RECIPIENT, 1));
+    let res = suite.execute(BOT, &[], intent_buy(1_000_000, signed.clone())).unwrap();
+    let buy = event(&res, BUY_EVENT);
+    assert_eq!(attribute(buy, "recipient"), RECIPIENT);
+    assert_eq!(attribute(buy, "input_amount"), "1000000");
+    assert_eq!(attribute(buy, "platform_fee"), "10000");
+
+    let balances: UserBalancesResponse = suite.query(QueryMsg::UserBalances { user: String::from(USER) });
+    assert!(balances.balances.is_empty());
+    let nonce: IntentNonceResponse = suite.query(QueryMsg::IntentNonce { user: String::from(USER), nonce: 1 });
+    assert!(nonce.used);
+
+    suite.execute(USER, &coins(1_000_000, "ujuno"), ExecuteMsg::Deposit {}).unwrap();
+    let err = suite.execute(BOT, &[], intent_buy(1_000_000, signed)).unwrap_err();
+    assert!(matches!(err, ContractError::IntentReplayed {}));
+}
+
+#[test]
+fn intent_must_be_signed_by_the_user_key() {
+    let mut suite = funded_suite();
+
+    let stranger = SigningKey::from_bytes(&[9u8; 32]).unwrap();
+    let err = suite.execute(BOT, &[], intent_buy(1_000_000, sign(&stranger, intent(RECIPIENT, 1)))).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidSignature {}));
+
+    // signed for one amount, submitted with another
+    let mut signed = sign(&user_key(), intent(RECIPIENT, 1));
+    signed.intent.max_juno_amount = Uint128::from(2_000_000u128);
+    let err = suite.execute(BOT, &[], intent_buy(1_000_000, signed)).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidSignature {}));
+
+    let mut other = intent(RECIPIENT, 1);
+    other.user = String::from("other");
+    let err = suite.execute(BOT, &[], intent_buy(1_000_000, sign(&user_key(), other))).unwrap_err();
+    assert!(matches!(err, ContractError::IntentKeyNotSet {}));
+}
+
+#[test]
+fn buy_must_stay_within_the_intent() {
+    let mut suite = funded_suite();
+    let signed = sign(&user_key(), intent(RECIPIENT, 1));
+
+    let err = suite.execute(BOT, &[], intent_buy(1_000_001, signed.clone())).unwrap_err();
+    assert!(matches!(err, ContractError::IntentViolated {}));
+
+    let mut buy = intent_buy(1_000_000, signed.clone());
+    if let ExecuteMsg::BuyToken { platform_fee_bips, .. } = &mut buy {
+        *platform_fee_bips = Some(Uint128::from(DEFAULT_FEE_BIPS + 1));
+    }
+    let err = suite.execute(BOT, &[], buy).unwrap_err();
+    assert!(matches!(err, ContractError::IntentViolated {}));
+
+    let mut buy = intent_buy(1_000_000, signed.clone());
+    if let ExecuteMsg::BuyToken { gas_estimate, .. } = &mut buy {
+        *gas_estimate = Uint128::from(5_001u128);
+    }
+    let err = suite.execute(BOT, &[], buy).unwrap_err();
+    assert!(matches!(err, ContractError::IntentViolated {}));
+
+    let mut buy = intent_buy(1_000_000, signed);
+    if let ExecuteMsg::BuyToken { recipient, .. } = &mut buy {
+        *recipient = String::from(BOT);
+    }
+    let err = suite.execute(BOT, &[], buy).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidRecipient {}));
+
+    // the deposit is untouched
+    let balances: UserBalancesResponse = suite.query(QueryMsg::UserBalances { user: String::from(USER) });
+    assert_eq!(balances.balances[0].amount, Uint128::from(1_000_000u128));
+}
+
+#[test]
+fn deposit_is_withdrawn_only_by_its_owner() {
+    let mut suite = funded_suite();
+
+    let withdraw = ExecuteMsg::Withdraw { denom: ujuno(), amount: Uint128::from(400_000u128) };
+    let err = suite.execute(BOT, &[], withdraw.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientUserBalance {}));
+
+    let res = suite.execute(USER, &[], withdraw).unwrap();
+    assert_eq!(response_attribute(&res, "balance"), "600000");
+    let err = suite.execute(USER, &[], ExecuteMsg::Withdraw { denom: ujuno(), amount: Uint128::from(600_001u128) }).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientUserBalance {}));
+}
+
+fn loyalty_config(points_per_ujuno: Decimal) -> ExecuteMsg {
+    ExecuteMsg::SetLoyaltyConfig {
+        token: String::from(LOYALTY),
+        points_per_ujuno,
+        ujuno_per_point: Decimal::one(),
+        epoch_seconds: 86_400,
+        epoch_cap: Uint128::from(1_000_000u128),
+    }
+}
+
+#[test]
+fn loyalty_points_go_to_self_funded_buys_up_to_the_fee() {
+    let mut suite = funded_suite();
+    suite.set_native(CONTRACT, "ujuno", 2_000_000);
+
+    let err = suite.execute(OWNER, &[], loyalty_config(Decimal::permille(50))).unwrap_err();
+    assert!(matches!(err, ContractError::ContractNotMinter {}));
+    suite.market.borrow_mut().minters.insert(String::from(LOYALTY), String::from(CONTRACT));
+    // a point is worth one ujuno, at 0.06 points per ujuno they would be worth more than the 5% max fee
+    let err = suite.execute(OWNER, &[], loyalty_config(Decimal::permille(60))).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidLoyaltyConfig {}));
+    suite.execute(OWNER, &[], loyalty_config(Decimal::permille(50))).unwrap();
+
+    // a shared buy earns nothing
+    let res = suite.execute(BOT, &[], buy_msg(1_000_000, 5_000)).unwrap();
+    assert_eq!(response_attribute(&res, "loyalty_points"), "0");
+
+    // nor does a deposit spent on someone else
+    suite.set_native(CONTRACT, "ujuno", 2_000_000);
+    suite.execute(USER, &coins(1_000_000, "ujuno"), ExecuteMsg::Deposit {}).unwrap();
+    suite.set_native(CONTRACT, "ujuno", 2_000_000);
+    let res = suite.execute(BOT, &[], intent_buy(1_000_000, sign(&user_key(), intent(RECIPIENT, 1)))).unwrap();
+    assert_eq!(response_attribute(&res, "loyalty_points"), "0");
+
+    // 50000 points at 0.05 per ujuno, capped at the 10000 ujuno fee paid
+    let res = suite.execute(BOT, &[], intent_buy(1_000_000, sign(&user_key(), intent(USER, 2)))).unwrap();
+    assert_eq!(response_attribute(&res, "loyalty_points"), "10000");
+    assert!(res.messages.iter().any(|msg| msg.msg == CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from(LOYALTY),
+        msg: to_binary(&Cw20ExecuteMsg::Mint { recipient: String::from(USER), amount: Uint128::from(10_000u128) }).unwrap(),
+        funds: vec![],
+    })));
+
+    let loyalty: LoyaltyResponse = suite.query(QueryMsg::Loyalty {});
+    assert_eq!(loyalty.epoch_minted, Uint128::from(10_000u128));
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use cosmwasm_std::{Addr, Decimal, Uint128};
+
+use buytoken::msg::{AdminResponse, BalancesResponse, ConfigResponse, DenomBalance, ExecuteMsg, QueryMsg, TradesResponse};
+
+use common::*;
+
+const POOL2: &str = "pool2";
+const BOT2: &str = "bot2";
+const RECIPIENT2: &str = "recipient2";
+
+fn trade_ids(suite: &Suite, msg: QueryMsg) -> Vec<u64> {
+    let trades: TradesResponse = suite.query(msg);
+    trades.trades.into_iter().map(|trade| trade.id).collect()
+}
+
+#[test]
+fn trades_are_paged_by_recipient_bot_and_pool() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 10_000_000);
+    suite.add_pool(POOL2, ujuno(), token(), 1_000_000_000, 1_000_000_000);
+    suite.register_pool(POOL2);
+    suite.execute(OWNER, &[], ExecuteMsg::SetBotRole { new_bot: String::from(BOT2), enabled: true }).unwrap();
+
+    suite.execute(BOT, &[], buy_msg(1_000_000, 0)).unwrap();
+    suite.execute(BOT, &[], buy_msg(1_000_000, 0)).unwrap();
+    let mut buy = buy_msg(1_000_000, 0);
+    if let ExecuteMsg::BuyToken { recipient, pool_address, .. } = &mut buy {
+        *recipient = String::from(RECIPIENT2);
+        *pool_address = String::from(POOL2);
+    }
+    suite.execute(BOT2, &[], buy).unwrap();
+
+    let by_recipient = |recipient: &str, start_after: Option<u64>, limit: Option<u32>| QueryMsg::TradesByRecipient { recipient: String::from(recipient), start_after, limit };
+    assert_eq!(trade_ids(&suite, by_recipient(RECIPIENT, None, None)), vec![1, 2]);
+    assert_eq!(trade_ids(&suite, by_recipient(RECIPIENT, None, Some(1))), vec![1]);
+    assert_eq!(trade_ids(&suite, by_recipient(RECIPIENT, Some(1), Some(1))), vec![2]);
+    assert_eq!(trade_ids(&suite, by_recipient(RECIPIENT, Some(2), None)), Vec::<u64>::new());
+    assert_eq!(trade_ids(&suite, by_recipient(RECIPIENT2, None, None)), vec![3]);
+
+    assert_eq!(trade_ids(&suite, QueryMsg::TradesByBot { bot: String::from(BOT), start_after: Some(1), limit: None }), vec![2]);
+    assert_eq!(trade_ids(&suite, QueryMsg::TradesByBot { bot: String::from(BOT2), start_after: None, limit: None }), vec![3]);
+
+    assert_eq!(trade_ids(&suite, QueryMsg::TradesByPool { pool: String::from(POOL), start_after: None, limit: None }), vec![1, 2]);
+    assert_eq!(trade_ids(&suite, QueryMsg::TradesByPool { pool: String::from(POOL2), start_after: None, limit: None }), vec![3]);
+}
+
+#[test]
+fn trade_pages_are_capped() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 100_000_000);
+    for _ in 0..35 {
+        suite.execute(BOT, &[], buy_msg(1_000_000, 0)).unwrap();
+    }
+
+    let page = |limit: Option<u32>| trade_ids(&suite, QueryMsg::TradesByBot { bot: String::from(BOT), start_after: None, limit });
+    assert_eq!(page(None).len(), 10);
+    assert_eq!(page(Some(100)), (1..=30).collect::<Vec<u64>>());
+}
+
+#[test]
+fn config_follows_the_admin_fee_and_gas_settings() {
+    let mut suite = Suite::new();
+    suite.execute(OWNER, &[], ExecuteMsg::SetFeeConfig { default_bips: Uint128::from(50u128), max_bips: Uint128::from(300u128), tiers: vec![] }).unwrap();
+    suite.execute(OWNER, &[], ExecuteMsg::SetGasConfig { gas_price: Decimal::permille(30), max_gas: Uint128::from(500_000u128) }).unwrap();
+    suite.execute(OWNER, &[], ExecuteMsg::SetAdmin { new_admin: String::from("admin2") }).unwrap();
+
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(config, ConfigResponse {
+        admin: Addr::unchecked("admin2"),
+        contract_address: Addr::unchecked(CONTRACT),
+        default_fee_bips: Uint128::from(50u128),
+        max_fee_bips: Uint128::from(300u128),
+        gas_price: Decimal::permille(30),
+        max_gas: Uint128::from(500_000u128),
+    });
+}
+
+#[test]
+fn balances_cover_native_and_cw20_denoms() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000);
+    suite.set_cw20(TOKEN, CONTRACT, 2_000);
+
+    let balances: BalancesResponse = suite.query(QueryMsg::Balances { denoms: vec![ujuno(), token(), atom()] });
+    assert_eq!(balances.balances, vec![
+        DenomBalance { denom: ujuno(), amount: Uint128::from(1_000u128) },
+        DenomBalance { denom: token(), amount: Uint128::from(2_000u128) },
+        DenomBalance { denom: atom(), amount: Uint128::zero() },
+    ]);
+
+    // the combined query stays for existing callers
+    let infos: AdminResponse = suite.query(QueryMsg::GetInfos { token: String::from("ujuno") });
+    assert_eq!(infos.admin, Addr::unchecked(OWNER));
+    assert_eq!(infos.token_balance, Uint128::from(1_000u128));
+}
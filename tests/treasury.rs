@@ -0,0 +1,314 @@
+mod common;
+
+use cosmwasm_std::{coins, from_binary, to_binary, BankMsg, Coin, CosmosMsg, DistributionMsg, StakingMsg, Uint128, WasmMsg};
+use cw20::Cw20ExecuteMsg;
+use wasmswap::msg::{ExecuteMsg as WasmswapExecuteMsg, TokenSelect};
+
+use buytoken::error::ContractError;
+use buytoken::msg::{BuybackResponse, ExecuteMsg, QueryMsg, StakingResponse, StatsResponse, StatusResponse};
+use buytoken::state::DenomTotal;
+use buytoken::util::get_input_price;
+
+use common::*;
+
+const TREASURY: &str = "treasury";
+const VALIDATOR2: &str = "validator2";
+
+/// 100000 ujuno of platform fees, the only ujuno left in the contract
+fn fee_suite() -> Suite {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 10_000_000);
+    suite.execute(BOT, &[], buy_msg(10_000_000, 0)).unwrap();
+    suite.set_native(CONTRACT, "ujuno", 100_000);
+    suite
+}
+
+fn pending_fee(suite: &Suite) -> Uint128 {
+    suite.query::<StatusResponse>(QueryMsg::Status {}).pending_platform_fee
+}
+
+fn ujuno_total(totals: &[DenomTotal]) -> Uint128 {
+    totals.iter().find(|total| total.denom == ujuno()).map_or(Uint128::zero(), |total| total.amount)
+}
+
+/// What POOL quotes for `amount` ujuno before anything moved its reserves
+fn quote(amount: u128) -> Uint128 {
+    get_input_price(Uint128::from(amount), Uint128::from(1_000_000_000u128), Uint128::from(1_000_000_000u128))
+}
+
+fn wasm_execute(msg: &CosmosMsg) -> (&str, &[Coin], WasmswapExecuteMsg) {
+    match msg {
+        CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, funds }) => (contract_addr, funds, from_binary(msg).unwrap()),
+        msg => panic!("unexpected message {:?}", msg),
+    }
+}
+
+fn cw20_message(token: &str, msg: Cw20ExecuteMsg) -> CosmosMsg {
+    CosmosMsg::Wasm(WasmMsg::Execute { contract_addr: String::from(token), msg: to_binary(&msg).unwrap(), funds: vec![] })
+}
+
+#[test]
+fn buyback_burns_what_the_swap_delivered() {
+    let mut suite = fee_suite();
+    suite.execute(OWNER, &[], ExecuteMsg::SetBuybackConfig { pool_address: String::from(POOL), max_slippage_bips: Uint128::from(100u128) }).unwrap();
+    suite.set_cw20(TOKEN, CONTRACT, 25);
+
+    let err = suite.execute(USER, &[], ExecuteMsg::BuybackAndBurn { amount: Uint128::from(40_000u128) }).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+    let err = suite.execute(BOT, &[], ExecuteMsg::BuybackAndBurn { amount: Uint128::from(100_001u128) }).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientFeeBalance {}));
+
+    let res = suite.execute(BOT, &[], ExecuteMsg::BuybackAndBurn { amount: Uint128::from(40_000u128) }).unwrap();
+    assert_eq!(response_attribute(&res, "quoted_amount"), quote(40_000).to_string());
+    assert_eq!(response_attribute(&res, "pending_platform_fee"), "60000");
+    // the swap itself may pay out down to the configured slippage
+    let (pool, funds, swap) = wasm_execute(&res.messages[0].msg);
+    assert_eq!((pool, funds), (POOL, coins(40_000, "ujuno").as_slice()));
+    assert_eq!(swap, WasmswapExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::from(40_000u128),
+        min_output: quote(40_000).multiply_ratio(99u128, 100u128),
+        expiration: None,
+    });
+    assert_eq!(pending_fee(&suite), Uint128::from(60_000u128));
+
+    // the pool pays out a little under the quote, the 25 held before are not burned
+    let delivered = quote(40_000) - Uint128::from(3u128);
+    suite.add_cw20(TOKEN, CONTRACT, delivered.u128());
+    let reply = suite.reply(&res).unwrap();
+    assert_eq!(reply.messages[0].msg, cw20_message(TOKEN, Cw20ExecuteMsg::Burn { amount: delivered }));
+
+    let buyback: BuybackResponse = suite.query(QueryMsg::Buyback {});
+    assert_eq!(buyback.total_burned, delivered);
+    let stats: StatsResponse = suite.query(QueryMsg::Stats {});
+    assert_eq!(ujuno_total(&stats.fees_withdrawn), Uint128::from(40_000u128));
+}
+
+#[test]
+fn buyback_of_nothing_burns_nothing() {
+    let mut suite = fee_suite();
+    suite.execute(OWNER, &[], ExecuteMsg::SetBuybackConfig { pool_address: String::from(POOL), max_slippage_bips: Uint128::from(100u128) }).unwrap();
+    suite.set_cw20(TOKEN, CONTRACT, 25);
+
+    let res = suite.execute(OWNER, &[], ExecuteMsg::BuybackAndBurn { amount: Uint128::from(40_000u128) }).unwrap();
+    let reply = suite.reply(&res).unwrap();
+    assert!(reply.messages.is_empty());
+    assert_eq!(response_attribute(&reply, "burned"), "0");
+
+    let buyback: BuybackResponse = suite.query(QueryMsg::Buyback {});
+    assert_eq!(buyback.total_burned, Uint128::zero());
+}
+
+#[test]
+fn compound_adds_the_swap_output_and_returns_the_unused_juno() {
+    let mut suite = fee_suite();
+    suite.execute(OWNER, &[], ExecuteMsg::SetLiquidityConfig { pool_address: String::from(POOL), max_slippage_bips: Uint128::from(100u128) }).unwrap();
+
+    let res = suite.execute(OWNER, &[], ExecuteMsg::CompoundFeesToLiquidity { amount: Uint128::from(40_000u128) }).unwrap();
+    let amount_out_min = quote(20_000).multiply_ratio(99u128, 100u128);
+    assert_eq!(response_attribute(&res, "amount_out_min"), amount_out_min.to_string());
+    let (pool, funds, swap) = wasm_execute(&res.messages[0].msg);
+    assert_eq!((pool, funds), (POOL, coins(20_000, "ujuno").as_slice()));
+    assert_eq!(swap, WasmswapExecuteMsg::Swap {
+        input_token: TokenSelect::Token1,
+        input_amount: Uint128::from(20_000u128),
+        min_output: amount_out_min,
+        expiration: None,
+    });
+    assert_eq!(pending_fee(&suite), Uint128::from(60_000u128));
+
+    // the swap moves the pool and delivers its quote
+    let delivered = quote(20_000).u128();
+    suite.set_pool_reserves(POOL, 1_000_020_000, 1_000_000_000 - delivered);
+    suite.add_cw20(TOKEN, CONTRACT, delivered);
+    let reply = suite.reply(&res).unwrap();
+
+    let token1_amount: u128 = response_attribute(&reply, "token1_amount").parse().unwrap();
+    let token2_amount: u128 = response_attribute(&reply, "token2_amount").parse().unwrap();
+    assert!(token1_amount <= 20_000 && token2_amount <= delivered);
+    // tokens the deposit could not pair stay in the contract, reported rather than lost track of
+    assert_eq!(response_attribute(&reply, "token_unused"), (delivered - token2_amount).to_string());
+    assert_eq!(reply.messages[0].msg, cw20_message(TOKEN, Cw20ExecuteMsg::IncreaseAllowance {
+        spender: String::from(POOL),
+        amount: Uint128::from(token2_amount),
+        expires: None,
+    }));
+    let (pool, funds, add) = wasm_execute(&reply.messages[1].msg);
+    assert_eq!((pool, funds), (POOL, coins(token1_amount, "ujuno").as_slice()));
+    assert!(matches!(add, WasmswapExecuteMsg::AddLiquidity { max_token2, .. } if max_token2 == Uint128::from(token2_amount)));
+
+    // the ujuno the deposit could not pair goes back to the fee ledger
+    let unused = 20_000 - token1_amount;
+    assert_eq!(pending_fee(&suite), Uint128::from(60_000 + unused));
+    let stats: StatsResponse = suite.query(QueryMsg::Stats {});
+    assert_eq!(ujuno_total(&stats.fees_withdrawn), Uint128::from(40_000 - unused));
+}
+
+#[test]
+fn compound_stays_within_the_fee_ledger() {
+    let mut suite = fee_suite();
+    let err = suite.execute(OWNER, &[], ExecuteMsg::CompoundFeesToLiquidity { amount: Uint128::from(40_000u128) }).unwrap_err();
+    assert!(matches!(err, ContractError::LiquidityNotConfigured {}));
+
+    suite.execute(OWNER, &[], ExecuteMsg::SetLiquidityConfig { pool_address: String::from(POOL), max_slippage_bips: Uint128::from(100u128) }).unwrap();
+    let err = suite.execute(BOT, &[], ExecuteMsg::CompoundFeesToLiquidity { amount: Uint128::from(40_000u128) }).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+    let err = suite.execute(OWNER, &[], ExecuteMsg::CompoundFeesToLiquidity { amount: Uint128::from(100_001u128) }).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientFeeBalance {}));
+
+    // fees the contract no longer holds cannot be compounded
+    suite.set_native(CONTRACT, "ujuno", 39_999);
+    let err = suite.execute(OWNER, &[], ExecuteMsg::CompoundFeesToLiquidity { amount: Uint128::from(40_000u128) }).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientFeeBalance {}));
+}
+
+fn remove_liquidity(amount: u128) -> ExecuteMsg {
+    ExecuteMsg::RemoveLiquidity {
+        amount: Uint128::from(amount),
+        min_token1: Uint128::from(1u128),
+        min_token2: Uint128::from(1u128),
+        to: String::from(TREASURY),
+    }
+}
+
+#[test]
+fn remove_liquidity_forwards_the_measured_proceeds() {
+    let mut suite = fee_suite();
+    suite.execute(OWNER, &[], ExecuteMsg::SetLiquidityConfig { pool_address: String::from(POOL), max_slippage_bips: Uint128::from(100u128) }).unwrap();
+    suite.set_cw20(LP_TOKEN, CONTRACT, 1_000);
+    suite.set_cw20(TOKEN, CONTRACT, 77);
+
+    let err = suite.execute(BOT, &[], remove_liquidity(400)).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+    let err = suite.execute(OWNER, &[], remove_liquidity(0)).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientToken {}));
+    let err = suite.execute(OWNER, &[], remove_liquidity(1_001)).unwrap_err();
+    assert!(matches!(err, ContractError::InsufficientToken {}));
+
+    let res = suite.execute(OWNER, &[], remove_liquidity(400)).unwrap();
+    assert_eq!(res.messages[0].msg, cw20_message(LP_TOKEN, Cw20ExecuteMsg::IncreaseAllowance {
+        spender: String::from(POOL),
+        amount: Uint128::from(400u128),
+        expires: None,
+    }));
+    let (pool, _, remove) = wasm_execute(&res.messages[1].msg);
+    assert_eq!(pool, POOL);
+    assert!(matches!(remove, WasmswapExecuteMsg::RemoveLiquidity { amount, .. } if amount == Uint128::from(400u128)));
+
+    // only what the pool paid out moves on, not the fees or tokens already held
+    suite.add_native(CONTRACT, "ujuno", 300);
+    suite.add_cw20(TOKEN, CONTRACT, 250);
+    let reply = suite.reply(&res).unwrap();
+    assert_eq!(reply.messages.len(), 2);
+    assert_eq!(reply.messages[0].msg, CosmosMsg::Bank(BankMsg::Send { to_address: String::from(TREASURY), amount: coins(300, "ujuno") }));
+    assert_eq!(reply.messages[1].msg, cw20_message(TOKEN, Cw20ExecuteMsg::Transfer { recipient: String::from(TREASURY), amount: Uint128::from(250u128) }));
+    assert_eq!(pending_fee(&suite), Uint128::from(100_000u128));
+}
+
+fn staking_suite() -> Suite {
+    let mut suite = fee_suite();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite.set_delegation(&[VALIDATOR, VALIDATOR2], &[(VALIDATOR, 500_000, 1_000), (VALIDATOR2, 200_000, 300)]);
+    suite
+}
+
+#[test]
+fn delegate_keeps_the_liquid_reserve() {
+    let mut suite = staking_suite();
+    let delegate = |validator: &str, amount: u128| ExecuteMsg::Delegate { validator: String::from(validator), amount: Uint128::from(amount) };
+
+    let err = suite.execute(BOT, &[], delegate(VALIDATOR, 1)).unwrap_err();
+    assert!(matches!(err, ContractError::Unauthorized {}));
+    let err = suite.execute(OWNER, &[], delegate(VALIDATOR, 0)).unwrap_err();
+    assert!(matches!(err, ContractError::NativeInputZero {}));
+    let err = suite.execute(OWNER, &[], delegate("unknown", 1)).unwrap_err();
+    assert!(matches!(err, ContractError::UnknownValidator {}));
+    let err = suite.execute(OWNER, &[], delegate(VALIDATOR, 1_000_001)).unwrap_err();
+    assert!(matches!(err, ContractError::LiquidReserveBreached {}));
+
+    suite.execute(OWNER, &[], ExecuteMsg::SetLiquidReserve { amount: Uint128::from(800_000u128) }).unwrap();
+    let err = suite.execute(OWNER, &[], delegate(VALIDATOR, 200_001)).unwrap_err();
+    assert!(matches!(err, ContractError::LiquidReserveBreached {}));
+
+    // the delegation change pays out the validator's rewards, which join the fee ledger
+    let res = suite.execute(OWNER, &[], delegate(VALIDATOR, 200_000)).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Staking(StakingMsg::Delegate { validator: String::from(VALIDATOR), amount: Coin::new(200_000, "ujuno") }));
+    assert_eq!(response_attribute(&res, "rewards"), "1000");
+    assert_eq!(pending_fee(&suite), Uint128::from(101_000u128));
+
+    let staking: StakingResponse = suite.query(QueryMsg::Staking {});
+    assert_eq!(staking.liquid_reserve, Uint128::from(800_000u128));
+    assert_eq!(staking.delegations.len(), 2);
+}
+
+#[test]
+fn undelegate_and_redelegate_book_the_rewards_paid_out() {
+    let mut suite = staking_suite();
+
+    let err = suite.execute(OWNER, &[], ExecuteMsg::Undelegate { validator: String::from(VALIDATOR), amount: Uint128::zero() }).unwrap_err();
+    assert!(matches!(err, ContractError::NativeInputZero {}));
+    let res = suite.execute(OWNER, &[], ExecuteMsg::Undelegate { validator: String::from(VALIDATOR), amount: Uint128::from(100_000u128) }).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Staking(StakingMsg::Undelegate { validator: String::from(VALIDATOR), amount: Coin::new(100_000, "ujuno") }));
+    assert_eq!(response_attribute(&res, "rewards"), "1000");
+    assert_eq!(response_attribute(&res, "pending_platform_fee"), "101000");
+
+    // both sides of a redelegation pay out
+    suite.set_delegation(&[VALIDATOR, VALIDATOR2], &[(VALIDATOR, 400_000, 50), (VALIDATOR2, 200_000, 300)]);
+    let redelegate = |amount: u128| ExecuteMsg::Redelegate {
+        src_validator: String::from(VALIDATOR2),
+        dst_validator: String::from(VALIDATOR),
+        amount: Uint128::from(amount),
+    };
+    let err = suite.execute(OWNER, &[], redelegate(0)).unwrap_err();
+    assert!(matches!(err, ContractError::NativeInputZero {}));
+    let res = suite.execute(OWNER, &[], redelegate(150_000)).unwrap();
+    assert_eq!(response_attribute(&res, "rewards"), "350");
+    assert_eq!(pending_fee(&suite), Uint128::from(101_350u128));
+
+    let stats: StatsResponse = suite.query(QueryMsg::Stats {});
+    assert_eq!(ujuno_total(&stats.fees_accrued), Uint128::from(101_350u128));
+}
+
+#[test]
+fn claimed_staking_rewards_join_the_fee_ledger() {
+    let mut suite = staking_suite();
+
+    let err = suite.execute(OWNER, &[], ExecuteMsg::ClaimStakingRewards { validator: Some(String::from("unknown")) }).unwrap_err();
+    assert!(matches!(err, ContractError::UnknownValidator {}));
+
+    let res = suite.execute(OWNER, &[], ExecuteMsg::ClaimStakingRewards { validator: Some(String::from(VALIDATOR2)) }).unwrap();
+    assert_eq!(res.messages[0].msg, CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward { validator: String::from(VALIDATOR2) }));
+    assert_eq!(response_attribute(&res, "rewards"), "300");
+
+    suite.set_delegation(&[VALIDATOR, VALIDATOR2], &[(VALIDATOR, 500_000, 7), (VALIDATOR2, 200_000, 5)]);
+    let res = suite.execute(OWNER, &[], ExecuteMsg::ClaimStakingRewards { validator: None }).unwrap();
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(response_attribute(&res, "rewards"), "12");
+    assert_eq!(pending_fee(&suite), Uint128::from(100_312u128));
+}
+
+#[test]
+fn stake_moves_off_a_validator_that_left_the_active_set() {
+    let mut suite = staking_suite();
+    // still delegated to, but no longer in the validator set
+    suite.set_delegation(&[VALIDATOR], &[(VALIDATOR, 500_000, 1_000), (VALIDATOR2, 200_000, 300)]);
+
+    let res = suite.execute(OWNER, &[], ExecuteMsg::Undelegate { validator: String::from(VALIDATOR2), amount: Uint128::from(50_000u128) }).unwrap();
+    assert_eq!(response_attribute(&res, "rewards"), "300");
+
+    let redelegate = |src: &str, dst: &str| ExecuteMsg::Redelegate {
+        src_validator: String::from(src),
+        dst_validator: String::from(dst),
+        amount: Uint128::from(50_000u128),
+    };
+    suite.execute(OWNER, &[], redelegate(VALIDATOR2, VALIDATOR)).unwrap();
+    let err = suite.execute(OWNER, &[], redelegate(VALIDATOR, VALIDATOR2)).unwrap_err();
+    assert!(matches!(err, ContractError::UnknownValidator {}));
+
+    // nothing to move off a validator the contract does not delegate to
+    suite.set_delegation(&[VALIDATOR, VALIDATOR2], &[(VALIDATOR, 500_000, 1_000)]);
+    let err = suite.execute(OWNER, &[], ExecuteMsg::Undelegate { validator: String::from(VALIDATOR2), amount: Uint128::from(1u128) }).unwrap_err();
+    assert!(matches!(err, ContractError::NoDelegation {}));
+    let err = suite.execute(OWNER, &[], redelegate(VALIDATOR2, VALIDATOR)).unwrap_err();
+    assert!(matches!(err, ContractError::NoDelegation {}));
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use cosmwasm_std::{to_binary, CosmosMsg, Uint128, Uint64, WasmMsg};
+use cw20::Cw20ExecuteMsg;
+
+use buytoken::error::ContractError;
+use buytoken::events::BUY_EVENT;
+use buytoken::msg::{BuyTokenResponse, ExecuteMsg, QueryMsg, VestingsResponse};
+
+use common::*;
+
+fn vested_msg(cliff: u64, duration: u64) -> ExecuteMsg {
+    ExecuteMsg::BuyTokenVested {
+        juno_amount: Uint128::from(1_000_000u128),
+        token_amount_per_native: Uint128::zero(),
+        slippage_bips: Uint128::zero(),
+        recipient: String::from(RECIPIENT),
+        pool_address: String::from(POOL),
+        platform_fee_bips: None,
+        gas_estimate: Uint128::zero(),
+        deadline: Uint64::new(u64::MAX),
+        cliff,
+        duration,
+    }
+}
+
+fn transfer(amount: u128) -> CosmosMsg {
+    CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: String::from(TOKEN),
+        msg: to_binary(&Cw20ExecuteMsg::Transfer { recipient: String::from(RECIPIENT), amount: Uint128::from(amount) }).unwrap(),
+        funds: vec![],
+    })
+}
+
+/// A vested buy of 1000 TOKEN, delivered to the contract
+fn vested_suite() -> Suite {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+    suite.set_cw20(TOKEN, CONTRACT, 50);
+
+    let res = suite.execute(BOT, &[], vested_msg(100, 1_000)).unwrap();
+    assert_eq!(attribute(event(&res, BUY_EVENT), "recipient"), RECIPIENT);
+    assert_eq!(response_attribute(&res, "cliff"), "100");
+
+    suite.add_cw20(TOKEN, CONTRACT, 1_000);
+    let reply = suite.reply(&res).unwrap();
+    assert_eq!(data::<BuyTokenResponse>(&reply).actual_output, Uint128::from(1_000u128));
+    assert_eq!(response_attribute(&reply, "vesting_id"), "1");
+    suite
+}
+
+#[test]
+fn vesting_terms_are_checked() {
+    let mut suite = Suite::new();
+    suite.set_native(CONTRACT, "ujuno", 1_000_000);
+
+    let err = suite.execute(BOT, &[], vested_msg(0, 0)).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidVesting {}));
+    let err = suite.execute(BOT, &[], vested_msg(1_001, 1_000)).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidVesting {}));
+}
+
+#[test]
+fn vesting_pays_out_linearly_after_the_cliff() {
+    let mut suite = vested_suite();
+    let start = suite.env.block.time.seconds();
+
+    let vestings: VestingsResponse = suite.query(QueryMsg::Vestings { recipient: String::from(RECIPIENT), start_after: None, limit: None });
+    let vesting = &vestings.vestings[0];
+    assert_eq!(vesting.vesting.total, Uint128::from(1_000u128));
+    assert_eq!((vesting.vesting.cliff, vesting.vesting.end), (start + 100, start + 1_000));
+    assert_eq!(vesting.claimable, Uint128::zero());
+
+    suite.env.block.time = suite.env.block.time.plus_seconds(99);
+    let err = suite.execute(RECIPIENT, &[], ExecuteMsg::ClaimVested {}).unwrap_err();
+    assert!(matches!(err, ContractError::NothingToClaim {}));
+
+    // halfway through the whole duration half has vested, the cliff only holds it back
+    suite.env.block.time = suite.env.block.time.plus_seconds(401);
+    let res = suite.execute(RECIPIENT, &[], ExecuteMsg::ClaimVested {}).unwrap();
+    assert_eq!(res.messages[0].msg, transfer(500));
+    assert_eq!(response_attribute(&res, "amount"), "500");
+
+    let err = suite.execute(RECIPIENT, &[], ExecuteMsg::ClaimVested {}).unwrap_err();
+    assert!(matches!(err, ContractError::NothingToClaim {}));
+
+    suite.env.block.time = suite.env.block.time.plus_seconds(10_000);
+    let res = suite.execute(RECIPIENT, &[], ExecuteMsg::ClaimVested {}).unwrap();
+    assert_eq!(res.messages[0].msg, transfer(500));
+
+    let vestings: VestingsResponse = suite.query(QueryMsg::Vestings { recipient: String::from(RECIPIENT), start_after: None, limit: None });
+    assert!(vestings.vestings.is_empty());
+}
+
+#[test]
+fn only_the_recipient_claims() {
+    let mut suite = vested_suite();
+    suite.env.block.time = suite.env.block.time.plus_seconds(1_000);
+
+    let err = suite.execute(BOT, &[], ExecuteMsg::ClaimVested {}).unwrap_err();
+    assert!(matches!(err, ContractError::NothingToClaim {}));
+    let res = suite.execute(RECIPIENT, &[], ExecuteMsg::ClaimVested {}).unwrap();
+    assert_eq!(res.messages[0].msg, transfer(1_000));
+}